@@ -0,0 +1,125 @@
+//! Admin password that protects the configuration web server.
+
+use crate::{
+    command::Command,
+    server::{ConfigData, ValidationErrors},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const SALT_LEN: usize = 16;
+const HASH_ROUNDS: u32 = 1024;
+
+/// Salted hash of the admin password as it is stored in the NVS.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdminCredentials {
+    salt: String,
+    hash: String,
+}
+
+impl AdminCredentials {
+    pub fn new(password: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        for chunk in salt.chunks_mut(4) {
+            let random = unsafe { esp_idf_sys::esp_random() };
+            chunk.copy_from_slice(&random.to_le_bytes()[..chunk.len()]);
+        }
+
+        Self {
+            salt: to_hex(&salt),
+            hash: to_hex(&hash_password(&salt, password)),
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        let salt = match from_hex(&self.salt) {
+            Some(salt) => salt,
+            None => return false,
+        };
+        let hash = to_hex(&hash_password(&salt, password));
+
+        // Compare in constant time to not leak how many characters matched.
+        hash.len() == self.hash.len()
+            && hash
+                .bytes()
+                .zip(self.hash.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+impl<'de> ConfigData<'de> for AdminCredentials {
+    fn key() -> &'static str {
+        "admin"
+    }
+}
+
+impl From<AdminCredentials> for Command {
+    fn from(credentials: AdminCredentials) -> Self {
+        Command::SaveAdminCredentials(credentials)
+    }
+}
+
+/// Form to set a new admin password. Only its hash is ever stored.
+#[derive(Deserialize)]
+pub struct AdminPassword {
+    pub password: String,
+}
+
+impl AdminPassword {
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            self.password.chars().count() >= 8,
+            "password",
+            "Das Passwort muss mindestens 8 Zeichen lang sein.",
+        );
+
+        errors.into_result()
+    }
+}
+
+/// Extracts the password of a HTTP Basic `Authorization` header.
+/// The user name is ignored as there is only a single admin.
+pub fn basic_auth_password(header: &str) -> Option<String> {
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+
+    Some(password.into())
+}
+
+fn hash_password(salt: &[u8], password: &str) -> [u8; 32] {
+    let mut hash: [u8; 32] = Sha256::new()
+        .chain_update(salt)
+        .chain_update(password.as_bytes())
+        .finalize()
+        .into();
+
+    // Make brute forcing a leaked hash more expensive.
+    for _ in 0..HASH_ROUNDS {
+        hash = Sha256::new()
+            .chain_update(hash)
+            .chain_update(salt)
+            .finalize()
+            .into();
+    }
+
+    hash
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,221 @@
+//! Measures the battery voltage and estimates the remaining charge.
+
+use embedded_hal::adc::{Channel, OneShot};
+use esp_idf_hal::adc::{PoweredAdc, ADC1};
+use serde::Serialize;
+
+/// Used if the reference voltage was not burned into the eFuses.
+const DEFAULT_VREF: u32 = 1100;
+/// The battery is connected through a 1/2 voltage divider.
+const DIVIDER: u32 = 2;
+const SAMPLES: u32 = 16;
+/// Weight of a new measurement in the smoothed voltage, in percent.
+const SMOOTHING: u32 = 30;
+/// Larger jumps are not smoothed, e.g. when the USB cable is plugged in.
+const MAX_SMOOTHED_STEP: u32 = 300;
+/// The board has no charger status pin. Above the end of charge voltage of a
+/// LiPo cell the board is powered by USB.
+const EXTERNAL_POWER_VOLTAGE: u16 = 4250;
+/// A charging cell sits at or below the end of charge voltage, so charging is
+/// detected by a rise of the idle voltage by more than this since the
+/// previous wake cycle. A drop by more than this ends it again.
+pub const CHARGE_THRESHOLD: u16 = 50;
+
+/// Below this charge the device refreshes less often and skips optional sources.
+const LOW_PERCENTAGE: u8 = 20;
+/// Below this charge the device stops refreshing to protect the cell.
+const CRITICAL_PERCENTAGE: u8 = 5;
+
+/// Discharge curve of a LiPo cell as (voltage in mV, charge in percent).
+const DISCHARGE_CURVE: &[(u16, u8)] = &[
+    (3270, 0),
+    (3610, 5),
+    (3690, 10),
+    (3710, 15),
+    (3730, 20),
+    (3750, 25),
+    (3770, 30),
+    (3790, 35),
+    (3800, 40),
+    (3820, 45),
+    (3840, 50),
+    (3850, 55),
+    (3870, 60),
+    (3910, 65),
+    (3950, 70),
+    (3980, 75),
+    (4020, 80),
+    (4080, 85),
+    (4110, 90),
+    (4150, 95),
+    (4200, 100),
+];
+
+/// Smoothed voltage of the previous measurement. Kept in the RTC memory so
+/// the smoothing works across deep sleep.
+#[link_section = ".rtc.data"]
+static mut SMOOTHED_VOLTAGE: u32 = 0;
+/// First measurement of the previous wake cycle, before the radio was
+/// turned on. Later measurements sag under the load of the radio.
+#[link_section = ".rtc.data"]
+static mut IDLE_VOLTAGE: u32 = 0;
+/// Whether the battery was charging in the previous wake cycle.
+#[link_section = ".rtc.data"]
+static mut CHARGING: bool = false;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerLevel {
+    Normal,
+    Low,
+    Critical,
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct BatteryStatus {
+    /// Battery voltage in mV.
+    pub voltage: u16,
+    pub percentage: u8,
+    /// Powered by USB, the battery is charging or not connected at all.
+    pub external_power: bool,
+}
+
+impl BatteryStatus {
+    pub fn from_voltage(voltage: u16) -> Self {
+        Self {
+            voltage,
+            percentage: percentage(voltage),
+            external_power: voltage >= EXTERNAL_POWER_VOLTAGE,
+        }
+    }
+
+    /// The power level is always normal if the battery was not measured yet.
+    pub fn level(&self) -> PowerLevel {
+        if self.external_power || self.voltage == 0 {
+            PowerLevel::Normal
+        } else if self.percentage < CRITICAL_PERCENTAGE {
+            PowerLevel::Critical
+        } else if self.percentage < LOW_PERCENTAGE {
+            PowerLevel::Low
+        } else {
+            PowerLevel::Normal
+        }
+    }
+}
+
+/// Linear interpolation between the points of the discharge curve.
+fn percentage(voltage: u16) -> u8 {
+    let (first, last) = (
+        DISCHARGE_CURVE[0],
+        DISCHARGE_CURVE[DISCHARGE_CURVE.len() - 1],
+    );
+
+    if voltage <= first.0 {
+        return first.1;
+    }
+    if voltage >= last.0 {
+        return last.1;
+    }
+
+    for window in DISCHARGE_CURVE.windows(2) {
+        let ((low_voltage, low), (high_voltage, high)) = (window[0], window[1]);
+
+        if voltage <= high_voltage {
+            let fraction =
+                (voltage - low_voltage) as u32 * 1000 / (high_voltage - low_voltage) as u32;
+            return low + ((high - low) as u32 * fraction / 1000) as u8;
+        }
+    }
+
+    last.1
+}
+
+pub struct Battery<P> {
+    pin: P,
+    adc: PoweredAdc<ADC1>,
+    characteristics: esp_idf_sys::esp_adc_cal_characteristics_t,
+    /// Charging is only checked with the first measurement of a wake cycle.
+    idle_measured: bool,
+}
+
+impl<P> Battery<P>
+where
+    P: Channel<ADC1>,
+    PoweredAdc<ADC1>: OneShot<ADC1, u16, P>,
+{
+    /// The pin has to be configured with 11 dB attenuation.
+    pub fn new(pin: P, adc: PoweredAdc<ADC1>) -> Self {
+        let mut characteristics = Default::default();
+
+        // Uses the calibration values from the eFuses if there are any.
+        let calibration = unsafe {
+            esp_idf_sys::esp_adc_cal_characterize(
+                esp_idf_sys::adc_unit_t_ADC_UNIT_1,
+                esp_idf_sys::adc_atten_t_ADC_ATTEN_DB_11,
+                esp_idf_sys::adc_bits_width_t_ADC_WIDTH_BIT_12,
+                DEFAULT_VREF,
+                &mut characteristics,
+            )
+        };
+        log::info!("ADC calibration: {}", calibration);
+
+        Self {
+            pin,
+            adc,
+            characteristics,
+            idle_measured: false,
+        }
+    }
+
+    /// Averages several samples and smoothes the result with the previous
+    /// measurements. Returns `None` if the ADC could not be read. The first
+    /// measurement after waking up has to be taken before the wifi is started.
+    pub fn measure(&mut self) -> Option<BatteryStatus> {
+        let mut sum = 0;
+        for _ in 0..SAMPLES {
+            let raw = self.adc.read(&mut self.pin).ok()?;
+            sum += unsafe {
+                esp_idf_sys::esp_adc_cal_raw_to_voltage(raw as u32, &self.characteristics)
+            };
+        }
+        let voltage = sum / SAMPLES * DIVIDER;
+
+        let previous = unsafe { SMOOTHED_VOLTAGE };
+        let step = if previous > voltage {
+            previous - voltage
+        } else {
+            voltage - previous
+        };
+        let smoothed = if previous == 0 || step > MAX_SMOOTHED_STEP {
+            voltage
+        } else {
+            (voltage * SMOOTHING + previous * (100 - SMOOTHING)) / 100
+        };
+        unsafe { SMOOTHED_VOLTAGE = smoothed };
+
+        if !self.idle_measured {
+            self.idle_measured = true;
+            update_charging(voltage);
+        }
+        let charging = unsafe { CHARGING };
+
+        let mut status = BatteryStatus::from_voltage(smoothed as u16);
+        status.external_power |= charging;
+
+        Some(status)
+    }
+}
+
+/// Compares the idle voltage with the one of the previous wake cycle.
+fn update_charging(idle_voltage: u32) {
+    let threshold = CHARGE_THRESHOLD as u32;
+
+    unsafe {
+        if IDLE_VOLTAGE != 0 && idle_voltage > IDLE_VOLTAGE + threshold {
+            CHARGING = true;
+        } else if idle_voltage + threshold < IDLE_VOLTAGE {
+            CHARGING = false;
+        }
+        IDLE_VOLTAGE = idle_voltage;
+    }
+}
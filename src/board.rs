@@ -0,0 +1,104 @@
+//! Pin mapping of the supported boards. The board is selected with a cargo feature.
+
+use anyhow::Result;
+use esp_idf_hal::gpio::Pins;
+
+#[cfg(not(any(feature = "board-ttgo-t5", feature = "board-waveshare-driver")))]
+compile_error!("Select a board with one of the features board-ttgo-t5 or board-waveshare-driver.");
+#[cfg(all(feature = "board-ttgo-t5", feature = "board-waveshare-driver"))]
+compile_error!("Enable only one of the features board-ttgo-t5 or board-waveshare-driver.");
+
+/// LILYGO TTGO T5 V2.3 with the panel connector, a button and a battery
+/// connector with a 1/2 voltage divider.
+#[cfg(feature = "board-ttgo-t5")]
+mod profile {
+    use esp_idf_hal::{adc::ADC1, gpio::*};
+
+    pub type Busy = Gpio4<Input>;
+    pub type Rst = Gpio16<Output>;
+    pub type Dc = Gpio17<Output>;
+    pub type Cs = Gpio5<Output>;
+    pub type Sclk = Gpio18<Unknown>;
+    pub type Mosi = Gpio23<Unknown>;
+    pub type Button = Gpio39<Input>;
+    pub type Vbat = Gpio35<Atten11dB<ADC1>>;
+
+    pub fn take(pins: Pins) -> anyhow::Result<super::BoardPins> {
+        Ok(super::BoardPins {
+            busy: pins.gpio4.into_input()?,
+            rst: pins.gpio16.into_output()?,
+            dc: pins.gpio17.into_output()?,
+            cs: pins.gpio5.into_output()?,
+            sclk: pins.gpio18,
+            mosi: pins.gpio23,
+            button: pins.gpio39.into_input()?,
+            vbat: pins.gpio35.into_analog_atten_11db()?,
+        })
+    }
+}
+
+/// Waveshare e-Paper ESP32 Driver Board. The board has no free button, the
+/// BOOT button cannot be used as holding it at power on starts the download
+/// mode. A button has to be connected between GPIO32 and GND, the internal
+/// pull-up is enabled. The battery has to be connected to GPIO36 with a 1/2
+/// voltage divider.
+#[cfg(feature = "board-waveshare-driver")]
+mod profile {
+    use esp_idf_hal::{adc::ADC1, gpio::*};
+
+    pub type Busy = Gpio25<Input>;
+    pub type Rst = Gpio26<Output>;
+    pub type Dc = Gpio27<Output>;
+    pub type Cs = Gpio15<Output>;
+    pub type Sclk = Gpio13<Unknown>;
+    pub type Mosi = Gpio14<Unknown>;
+    pub type Button = Gpio32<Input>;
+    pub type Vbat = Gpio36<Atten11dB<ADC1>>;
+
+    pub fn take(pins: Pins) -> anyhow::Result<super::BoardPins> {
+        Ok(super::BoardPins {
+            busy: pins.gpio25.into_input()?,
+            rst: pins.gpio26.into_output()?,
+            dc: pins.gpio27.into_output()?,
+            cs: pins.gpio15.into_output()?,
+            sclk: pins.gpio13,
+            mosi: pins.gpio14,
+            button: take_button(pins.gpio32)?,
+            vbat: pins.gpio36.into_analog_atten_11db()?,
+        })
+    }
+
+    /// The pull-up of the RTC domain keeps the pin high during deep sleep.
+    fn take_button(pin: Gpio32<Unknown>) -> anyhow::Result<Button> {
+        let button = pin.into_input()?;
+
+        unsafe {
+            esp_idf_sys::esp!(esp_idf_sys::gpio_pullup_en(button.pin()))?;
+            esp_idf_sys::esp!(esp_idf_sys::rtc_gpio_pullup_en(button.pin()))?;
+        }
+
+        Ok(button)
+    }
+}
+
+pub use profile::{Busy, Button, Cs, Dc, Mosi, Rst, Sclk, Vbat};
+
+pub struct BoardPins {
+    pub busy: Busy,
+    pub rst: Rst,
+    pub dc: Dc,
+    pub cs: Cs,
+    pub sclk: Sclk,
+    pub mosi: Mosi,
+    /// Wakes the device up and starts the setup mode. Has to be an RTC GPIO
+    /// that is pulled low when pressed.
+    pub button: Button,
+    /// Battery voltage behind a 1/2 voltage divider.
+    pub vbat: Vbat,
+}
+
+impl BoardPins {
+    pub fn take(pins: Pins) -> Result<Self> {
+        profile::take(pins)
+    }
+}
@@ -0,0 +1,45 @@
+//! Keeps the fetched data on the flash so it survives the deep sleep.
+
+use crate::storage::BASE_DIR;
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+};
+
+/// The FAT partition only supports 8.3 file names.
+fn path(name: &str) -> String {
+    format!("{}/cache/{}.dat", BASE_DIR, name)
+}
+
+pub fn store<T>(name: &str, value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    fs::create_dir_all(format!("{}/cache", BASE_DIR))
+        .context("Could not create the cache directory.")?;
+
+    let file = File::create(path(name))
+        .with_context(|| format!("Could not create cache file for \"{}\".", name))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value)
+        .with_context(|| format!("Could not write cache file for \"{}\".", name))?;
+    writer
+        .flush()
+        .with_context(|| format!("Could not write cache file for \"{}\".", name))?;
+
+    Ok(())
+}
+
+pub fn load<T>(name: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let file =
+        File::open(path(name)).with_context(|| format!("No cached data for \"{}\".", name))?;
+    let value = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Could not read cache file for \"{}\".", name))?;
+
+    Ok(value)
+}
@@ -1,11 +1,78 @@
 //! Command type that the main task listenes for.
 
-use crate::server::{PersonalData, RssData, StockData, WifiData};
+use crate::{
+    auth::AdminCredentials,
+    graphics::{pages::PageType, refresh::DisplayConfig},
+    schedule::Schedule,
+    server::{PersonalData, RssData, StockData, WifiData},
+    wifi::AccessPointConfig,
+};
+use std::sync::mpsc::Sender;
 
 pub enum Command {
     SavePersonalConfig(PersonalData),
     SaveWifiConfig(WifiData),
     SaveRssConfig(RssData),
     SaveStockConfig(StockData),
+    SaveScheduleConfig(Schedule),
+    SaveDisplayConfig(DisplayConfig),
+    SaveAdminCredentials(AdminCredentials),
+    SaveAccessPointConfig(AccessPointConfig),
     SwitchPage,
+    SetPage(PageType),
+}
+
+/// Data sources the fetching thread can be asked to refresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    All,
+    /// All sources except the optional ones, to save energy on a low battery.
+    Essential,
+    Feed,
+    Weather,
+    Stock,
+}
+
+impl Refresh {
+    /// The single data sources that are refreshed.
+    pub fn sources(self) -> &'static [Refresh] {
+        match self {
+            Refresh::All => &[Refresh::Feed, Refresh::Weather, Refresh::Stock],
+            Refresh::Essential => &[Refresh::Feed, Refresh::Weather],
+            Refresh::Feed => &[Refresh::Feed],
+            Refresh::Weather => &[Refresh::Weather],
+            Refresh::Stock => &[Refresh::Stock],
+        }
+    }
+
+    pub fn includes(self, source: Refresh) -> bool {
+        self.sources().contains(&source)
+    }
+
+    /// The data source that is shown on the page, if any.
+    pub fn for_page(page: &PageType) -> Option<Self> {
+        match page {
+            PageType::FeedPage(_) => Some(Refresh::Feed),
+            PageType::WeatherPage(_) => Some(Refresh::Weather),
+            PageType::StockPage(_) => Some(Refresh::Stock),
+            PageType::SystemPage(_)
+            | PageType::ExamplePage(_)
+            | PageType::ConfigPage(_)
+            | PageType::LowBatteryPage(_) => None,
+        }
+    }
+}
+
+/// Asks the fetching thread for new data.
+pub struct RefreshRequest {
+    pub source: Refresh,
+    /// Notified once the data was fetched. The display is then not updated
+    /// by the fetching thread, this is left to whoever waits for it.
+    pub done: Option<Sender<()>>,
+}
+
+impl From<Refresh> for RefreshRequest {
+    fn from(source: Refresh) -> Self {
+        Self { source, done: None }
+    }
 }
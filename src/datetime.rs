@@ -42,3 +42,12 @@ pub fn get_datetime() -> Result<PrimitiveDateTime> {
 
     Ok(PrimitiveDateTime::new(date, time))
 }
+
+/// The local time of day or `None` if the clock was never synchronized.
+pub fn get_local_time() -> Option<Time> {
+    // Without SNTP the clock starts at the unix epoch.
+    get_datetime()
+        .ok()
+        .filter(|datetime| datetime.year() >= 2022)
+        .map(|datetime| datetime.time())
+}
@@ -1,4 +1,6 @@
 //! Some definitions that are used throughout the application.
 
-pub const AP_SSID: &str = "ESP-Feed";
-pub const AP_PASSWORD: &str = "38294446";
+use std::net::Ipv4Addr;
+
+/// Address of the access point in setup mode.
+pub const AP_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
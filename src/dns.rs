@@ -0,0 +1,106 @@
+//! Minimal DNS server for the captive portal in setup mode.
+//!
+//! Every A query is answered with the address of the access point, so the
+//! captive portal detection of phones and laptops ends up at the settings.
+
+use crate::definitions::AP_IP;
+use anyhow::{Context, Result};
+use log::*;
+use std::net::UdpSocket;
+
+const DNS_PORT: u16 = 53;
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_ANY: u16 = 255;
+const TTL: u32 = 60;
+
+pub fn start_captive_dns() -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", DNS_PORT)).context("Could not bind DNS socket.")?;
+
+    std::thread::Builder::new()
+        .name("DNS".into())
+        .stack_size(4096)
+        .spawn(move || {
+            let mut buf = [0u8; 512];
+
+            loop {
+                let (len, source) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        warn!("Could not receive DNS query: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(response) = answer_query(&buf[..len]) {
+                    if let Err(e) = socket.send_to(&response, source) {
+                        warn!("Could not send DNS response: {}", e);
+                    }
+                }
+            }
+        })
+        .context("Could not create DNS thread.")?;
+
+    info!("Captive portal DNS server started.");
+
+    Ok(())
+}
+
+/// Builds the response to a query. Everything that is not a standard query
+/// with a single question is ignored.
+fn answer_query(query: &[u8]) -> Option<Vec<u8>> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0x0f;
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+
+    if is_response || opcode != 0 || question_count != 1 {
+        return None;
+    }
+
+    // Skip the labels of the queried name.
+    let mut pos = HEADER_LEN;
+    loop {
+        let label_len = *query.get(pos)? as usize;
+        pos += 1;
+
+        if label_len == 0 {
+            break;
+        }
+        pos += label_len;
+    }
+
+    let question_end = pos + 4;
+    let question = query.get(HEADER_LEN..question_end)?;
+    let query_type = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let answer_count = matches!(query_type, TYPE_A | TYPE_ANY) as u16;
+
+    let mut response = Vec::with_capacity(question_end + 16);
+
+    // Header: same id, response with authoritative answer and the
+    // recursion desired bit of the query.
+    response.extend_from_slice(&query[0..2]);
+    response.extend_from_slice(&(0x8480 | (flags & 0x0100)).to_be_bytes());
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&answer_count.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+
+    response.extend_from_slice(question);
+
+    if answer_count > 0 {
+        // Pointer to the name in the question section.
+        response.extend_from_slice(&0xc00cu16.to_be_bytes());
+        response.extend_from_slice(&TYPE_A.to_be_bytes());
+        response.extend_from_slice(&1u16.to_be_bytes()); // Class IN
+        response.extend_from_slice(&TTL.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&AP_IP.octets());
+    }
+
+    Some(response)
+}
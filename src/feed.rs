@@ -1,5 +1,6 @@
 //! Fetches the feeds and provides the FeedController.
 
+use crate::cache;
 use anyhow::{bail, Context, Result};
 use embedded_svc::{
     http::client::{Client, Request},
@@ -7,6 +8,7 @@ use embedded_svc::{
 };
 use esp_idf_svc::http::client::EspHttpClient;
 use log::*;
+use serde::{Deserialize, Serialize};
 use std::io::{BufRead, BufReader};
 use url::Url;
 
@@ -24,11 +26,36 @@ use url::Url;
 //     }
 // }
 
+#[derive(Serialize, Deserialize)]
 pub struct Feed {
     pub title: String,
     pub headlines: Vec<String>,
+    /// Whether the headline at the same index was not in the feed before the
+    /// last refresh.
+    #[serde(default)]
+    pub new: Vec<bool>,
     // scroll position...
 }
+
+impl Feed {
+    pub fn is_new(&self, headline: usize) -> bool {
+        self.new.get(headline).copied().unwrap_or(false)
+    }
+
+    /// Marks the headlines that are not in the previous version of the feed.
+    /// Nothing is marked if the feed was not fetched before.
+    fn mark_new(&mut self, previous: Option<&Feed>) {
+        self.new = match previous {
+            Some(previous) => self
+                .headlines
+                .iter()
+                .map(|headline| !previous.headlines.contains(headline))
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+}
+
 pub struct FeedController {
     feeds: Vec<Feed>,
     urls: Vec<Url>,
@@ -43,7 +70,7 @@ impl FeedController {
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        self.feeds.clear();
+        let previous_feeds = std::mem::take(&mut self.feeds);
 
         let mut client = EspHttpClient::new_default().context("Failed to create HTTP client.")?;
 
@@ -54,8 +81,10 @@ impl FeedController {
             match parse_rss_feed(&mut response_reader)
                 .with_context(|| format!("Could not retrieve/parse feed {}", url))
             {
-                Ok(feed) => {
+                Ok(mut feed) => {
                     info!("Got new feed: {}", feed.title);
+                    feed.mark_new(previous_feeds.iter().find(|f| f.title == feed.title));
+
                     for line in &feed.headlines {
                         info!("{}", line);
                     }
@@ -71,10 +100,26 @@ impl FeedController {
         Ok(())
     }
 
+    /// Replaces the feed urls and drops the feeds of the previous ones.
+    pub fn set_urls(&mut self, urls: Vec<Url>) {
+        self.urls = urls;
+        self.feeds.clear();
+    }
+
     pub fn urls_mut(&mut self) -> &mut Vec<Url> {
         &mut self.urls
     }
 
+    pub fn store_cache(&self) -> Result<()> {
+        cache::store("feed", &self.feeds)
+    }
+
+    pub fn load_cache(&mut self) -> Result<()> {
+        self.feeds = cache::load("feed")?;
+
+        Ok(())
+    }
+
     pub fn feeds(&self) -> &[Feed] {
         &self.feeds
     }
@@ -138,5 +183,9 @@ fn parse_rss_feed(reader: &mut impl BufRead) -> Result<Feed> {
         }
     }
 
-    Ok(Feed { title, headlines })
+    Ok(Feed {
+        title,
+        headlines,
+        new: Vec::new(),
+    })
 }
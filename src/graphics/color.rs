@@ -0,0 +1,29 @@
+//! Colors the pages are drawn with.
+
+use embedded_graphics::pixelcolor::{BinaryColor, PixelColor};
+
+/// Whether the panel has a third color. It is red or yellow, depending on
+/// the panel.
+pub const CHROMATIC: bool = cfg!(any(feature = "epd2in13bc", feature = "epd2in9bc"));
+
+/// Monochrome panels show `Red` as a checkerboard pattern. Text should use
+/// `style::highlight_text`, which is inverted on these panels instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    White,
+    Black,
+    Red,
+}
+
+impl PixelColor for Color {
+    type Raw = ();
+}
+
+impl From<BinaryColor> for Color {
+    fn from(color: BinaryColor) -> Self {
+        match color {
+            BinaryColor::Off => Color::White,
+            BinaryColor::On => Color::Black,
+        }
+    }
+}
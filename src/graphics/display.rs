@@ -1,104 +1,446 @@
+//! The e-paper panel. The panel type is selected with a cargo feature.
+
+use super::color::Color;
+use crate::board::{Busy, Cs, Dc, Mosi, Rst, Sclk};
 use anyhow::Result;
-use embedded_graphics::{draw_target::DrawTarget, pixelcolor::BinaryColor, prelude::*};
-use epd_waveshare::{
-    epd2in13_v2::{Display2in13, Epd2in13},
-    prelude::*,
+use core::convert::Infallible;
+use embedded_graphics::{
+    draw_target::DrawTarget, pixelcolor::BinaryColor, prelude::*, primitives::Rectangle,
 };
+use epd_waveshare::{color::Color as EpdColor, prelude::*};
 use esp_idf_hal::{
     delay, gpio,
     spi::{config::Config, Master, Pins, SPI3},
 };
 
-type SpiMaster = Master<
-    SPI3,
-    gpio::Gpio18<gpio::Unknown>,
-    gpio::Gpio23<gpio::Unknown>,
-    gpio::Gpio1<gpio::Input>,
-    gpio::Gpio5<gpio::Output>,
->;
-
-type Edp2in13Display = Epd2in13<
-    SpiMaster,
-    gpio::Gpio5<gpio::Output>,
-    gpio::Gpio4<gpio::Input>,
-    gpio::Gpio17<gpio::Output>,
-    gpio::Gpio16<gpio::Output>,
-    delay::Ets,
->;
+#[cfg(feature = "epd2in13")]
+use epd_waveshare::epd2in13_v2::{Display2in13 as Buffer, Epd2in13 as Driver, HEIGHT, WIDTH};
+#[cfg(feature = "epd2in13bc")]
+use epd_waveshare::epd2in13bc::{Display2in13bc as Buffer, Epd2in13bc as Driver, HEIGHT, WIDTH};
+#[cfg(feature = "epd2in9")]
+use epd_waveshare::epd2in9_v2::{Display2in9 as Buffer, Epd2in9 as Driver, HEIGHT, WIDTH};
+#[cfg(feature = "epd2in9bc")]
+use epd_waveshare::epd2in9bc::{Display2in9bc as Buffer, Epd2in9bc as Driver, HEIGHT, WIDTH};
+#[cfg(feature = "epd4in2")]
+use epd_waveshare::epd4in2::{Display4in2 as Buffer, Epd4in2 as Driver, HEIGHT, WIDTH};
+
+#[cfg(not(any(
+    feature = "epd2in13",
+    feature = "epd2in13bc",
+    feature = "epd2in9",
+    feature = "epd2in9bc",
+    feature = "epd4in2"
+)))]
+compile_error!(
+    "Select a panel with one of the features epd2in13, epd2in13bc, epd2in9, epd2in9bc or epd4in2."
+);
+
+#[cfg(any(
+    all(feature = "epd2in13", feature = "epd2in13bc"),
+    all(feature = "epd2in13", feature = "epd2in9"),
+    all(feature = "epd2in13", feature = "epd2in9bc"),
+    all(feature = "epd2in13", feature = "epd4in2"),
+    all(feature = "epd2in13bc", feature = "epd2in9"),
+    all(feature = "epd2in13bc", feature = "epd2in9bc"),
+    all(feature = "epd2in13bc", feature = "epd4in2"),
+    all(feature = "epd2in9", feature = "epd2in9bc"),
+    all(feature = "epd2in9", feature = "epd4in2"),
+    all(feature = "epd2in9bc", feature = "epd4in2")
+))]
+compile_error!(
+    "Enable only one of the features epd2in13, epd2in13bc, epd2in9, epd2in9bc or epd4in2. \
+     The default epd2in13 has to be disabled with --no-default-features."
+);
+
+/// The small panels are mounted in portrait orientation, but used in landscape
+/// by default. The rotation of the settings is added to this one.
+#[cfg(not(feature = "epd4in2"))]
+const MOUNT_ROTATION: u16 = 90;
+#[cfg(feature = "epd4in2")]
+const MOUNT_ROTATION: u16 = 0;
+
+type SpiMaster = Master<SPI3, Sclk, Mosi, gpio::Gpio1<gpio::Input>, Cs>;
+
+type PanelDriver = Driver<SpiMaster, Cs, Busy, Dc, Rst, delay::Ets>;
+
+/// An e-paper panel together with its frame buffer.
+pub trait Panel {
+    type Frame: DrawTarget<Color = Color, Error = Infallible>;
+
+    fn frame(&mut self) -> &mut Self::Frame;
+
+    /// Content of the frame as it is sent to the panel.
+    fn buffer(&self) -> &[u8];
+
+    /// Size of the drawing area in the orientation the panel is used in.
+    fn size(&self) -> Size;
+
+    /// Whether the panel can update without flashing the whole screen.
+    ///
+    /// Only the 2.13" v2 panel does. The 2.9" v2 panel can do a quick refresh
+    /// as well, but its driver takes the old and the new frame separately
+    /// instead of a refresh LUT and a base buffer, which is not wired up yet.
+    /// The three color panels and the 4.2" panel only have a full refresh.
+    fn supports_quick_refresh(&self) -> bool;
+
+    fn clear(&mut self);
+
+    /// Rotation in degrees clockwise, relative to the landscape orientation.
+    /// Inverted frames are drawn white on black.
+    fn set_orientation(&mut self, rotation: u16, inverted: bool);
+
+    fn wake_up(&mut self) -> Result<()>;
+
+    fn sleep(&mut self) -> Result<()>;
+
+    /// Shows the frame with a full refresh which removes any ghosting.
+    fn show(&mut self) -> Result<()>;
+
+    /// Shows the frame with a quick refresh. `base` is the frame that is
+    /// currently shown on the panel.
+    fn show_quick(&mut self, base: &[u8]) -> Result<()>;
+
+    /// Like `show_quick`, but only updates the given area of the frame.
+    fn show_quick_area(&mut self, base: &[u8], area: Rectangle) -> Result<()>;
+}
 
 pub struct EpdDisplay {
-    pub epd2in13: Edp2in13Display,
-    pub master: SpiMaster,
-    pub display: Box<RotatedDisplay2in13>,
-    pub delay: delay::Ets,
+    driver: PanelDriver,
+    master: SpiMaster,
+    frame: Box<Frame>,
+    delay: delay::Ets,
+}
+
+impl Panel for EpdDisplay {
+    type Frame = Frame;
+
+    fn frame(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+
+    fn buffer(&self) -> &[u8] {
+        self.frame.buffer()
+    }
+
+    fn size(&self) -> Size {
+        self.frame.size()
+    }
+
+    fn supports_quick_refresh(&self) -> bool {
+        cfg!(feature = "epd2in13")
+    }
+
+    fn clear(&mut self) {
+        self.frame.clear();
+    }
+
+    fn set_orientation(&mut self, rotation: u16, inverted: bool) {
+        self.frame.set_orientation(rotation, inverted);
+    }
+
+    fn wake_up(&mut self) -> Result<()> {
+        self.driver.wake_up(&mut self.master, &mut self.delay)?;
+
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        self.driver.sleep(&mut self.master, &mut self.delay)?;
+
+        Ok(())
+    }
+
+    #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+    fn show(&mut self) -> Result<()> {
+        self.driver.update_color_frame(
+            &mut self.master,
+            self.frame.buffer(),
+            self.frame.chromatic_buffer(),
+        )?;
+        self.driver
+            .display_frame(&mut self.master, &mut self.delay)?;
+
+        Ok(())
+    }
+
+    #[cfg(not(any(feature = "epd2in13bc", feature = "epd2in9bc")))]
+    fn show(&mut self) -> Result<()> {
+        #[cfg(feature = "epd2in13")]
+        self.driver
+            .set_refresh(&mut self.master, &mut self.delay, RefreshLut::Full)?;
+
+        self.driver.update_and_display_frame(
+            &mut self.master,
+            self.frame.buffer(),
+            &mut self.delay,
+        )?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "epd2in13")]
+    fn show_quick(&mut self, base: &[u8]) -> Result<()> {
+        self.driver
+            .set_refresh(&mut self.master, &mut self.delay, RefreshLut::Quick)?;
+        self.driver
+            .set_partial_base_buffer(&mut self.master, &mut self.delay, base)?;
+        self.driver.update_and_display_frame(
+            &mut self.master,
+            self.frame.buffer(),
+            &mut self.delay,
+        )?;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "epd2in13"))]
+    fn show_quick(&mut self, _base: &[u8]) -> Result<()> {
+        self.show()
+    }
+
+    #[cfg(feature = "epd2in13")]
+    fn show_quick_area(&mut self, base: &[u8], area: Rectangle) -> Result<()> {
+        let window = self.frame.native_window(area);
+        let window_buffer = self.frame.window_buffer(&window);
+
+        self.driver
+            .set_refresh(&mut self.master, &mut self.delay, RefreshLut::Quick)?;
+        self.driver
+            .set_partial_base_buffer(&mut self.master, &mut self.delay, base)?;
+        self.driver.update_partial_frame(
+            &mut self.master,
+            &window_buffer,
+            window.x,
+            window.y,
+            window.width,
+            window.height,
+        )?;
+        self.driver
+            .display_frame(&mut self.master, &mut self.delay)?;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "epd2in13"))]
+    fn show_quick_area(&mut self, base: &[u8], _area: Rectangle) -> Result<()> {
+        self.show_quick(base)
+    }
+}
+
+/// Frame buffer that reports its size in the rotated orientation.
+pub struct Frame {
+    buffer: Buffer,
+    /// Red or yellow pixels of three color panels.
+    #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+    chromatic: Buffer,
+    inverted: bool,
 }
 
-pub struct RotatedDisplay2in13(Display2in13);
+/// Area of the panel in its native orientation. The horizontal bounds are
+/// aligned to whole bytes of the buffer, as required for partial updates.
+pub struct NativeWindow {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
 
-impl RotatedDisplay2in13 {
+impl Frame {
     pub fn new() -> Self {
-        let mut display = Display2in13::default();
-        display.set_rotation(DisplayRotation::Rotate90);
-        Self(display)
+        let mut frame = Self {
+            buffer: Buffer::default(),
+            #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+            chromatic: Buffer::default(),
+            inverted: false,
+        };
+        frame.set_orientation(0, false);
+
+        frame
+    }
+
+    pub fn set_orientation(&mut self, rotation: u16, inverted: bool) {
+        let rotation = match (MOUNT_ROTATION + rotation) % 360 {
+            90 => DisplayRotation::Rotate90,
+            180 => DisplayRotation::Rotate180,
+            270 => DisplayRotation::Rotate270,
+            _ => DisplayRotation::Rotate0,
+        };
+
+        self.buffer.set_rotation(rotation);
+        #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+        self.chromatic.set_rotation(rotation);
+        self.inverted = inverted;
+    }
+
+    /// Fills the frame with the background color.
+    pub fn clear(&mut self) {
+        let background = if self.inverted {
+            EpdColor::Black
+        } else {
+            EpdColor::White
+        };
+
+        self.buffer.clear_buffer(background);
+        #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+        self.chromatic.clear_buffer(EpdColor::White);
+    }
+
+    #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+    pub fn chromatic_buffer(&self) -> &[u8] {
+        self.chromatic.buffer()
+    }
+
+    /// The pixel in the black buffer, which is inverted in the dark mode.
+    fn black_pixel(&self, color: Color) -> BinaryColor {
+        let pixel = if color == Color::Black {
+            BinaryColor::On
+        } else {
+            BinaryColor::Off
+        };
+
+        if self.inverted {
+            pixel.invert()
+        } else {
+            pixel
+        }
+    }
+
+    /// Red pixels are never inverted and clear the black pixel so the
+    /// color is visible.
+    #[cfg(any(feature = "epd2in13bc", feature = "epd2in9bc"))]
+    fn draw_pixel(&mut self, point: Point, color: Color) -> Result<(), Infallible> {
+        let (black, chromatic) = match color {
+            Color::Red => (BinaryColor::Off, BinaryColor::On),
+            _ => (self.black_pixel(color), BinaryColor::Off),
+        };
+
+        Pixel(point, black).draw(&mut self.buffer)?;
+        Pixel(point, chromatic).draw(&mut self.chromatic)
+    }
+
+    /// Red areas are shown as a checkerboard pattern.
+    #[cfg(not(any(feature = "epd2in13bc", feature = "epd2in9bc")))]
+    fn draw_pixel(&mut self, point: Point, color: Color) -> Result<(), Infallible> {
+        let black = match color {
+            Color::Red if (point.x + point.y).rem_euclid(2) == 0 => BinaryColor::On,
+            Color::Red => BinaryColor::Off,
+            _ => self.black_pixel(color),
+        };
+
+        Pixel(point, black).draw(&mut self.buffer)
+    }
+
+    /// Maps a rotated point to the native orientation of the panel.
+    fn native_point(&self, point: Point) -> (u32, u32) {
+        let (x, y) = (point.x as u32, point.y as u32);
+
+        match self.buffer.rotation() {
+            DisplayRotation::Rotate0 => (x, y),
+            DisplayRotation::Rotate90 => (WIDTH - 1 - y, x),
+            DisplayRotation::Rotate180 => (WIDTH - 1 - x, HEIGHT - 1 - y),
+            DisplayRotation::Rotate270 => (y, HEIGHT - 1 - x),
+        }
+    }
+
+    /// The native window that covers the given area of the rotated display.
+    pub fn native_window(&self, area: Rectangle) -> NativeWindow {
+        let bottom_right = area.bottom_right().unwrap_or(area.top_left);
+        let (x0, y0) = self.native_point(area.top_left);
+        let (x1, y1) = self.native_point(bottom_right);
+
+        let x = x0.min(x1) / 8 * 8;
+        let y = y0.min(y1);
+
+        NativeWindow {
+            x,
+            y,
+            width: (x0.max(x1) / 8 + 1) * 8 - x,
+            height: y0.max(y1) - y + 1,
+        }
+    }
+
+    /// Copies the part of the buffer that belongs to the window.
+    pub fn window_buffer(&self, window: &NativeWindow) -> Vec<u8> {
+        let line_width = ((WIDTH + 7) / 8) as usize;
+        let (start, end) = (
+            (window.x / 8) as usize,
+            ((window.x + window.width) / 8) as usize,
+        );
+
+        self.buffer
+            .buffer()
+            .chunks(line_width)
+            .skip(window.y as usize)
+            .take(window.height as usize)
+            .flat_map(|line| &line[start..end])
+            .copied()
+            .collect()
     }
 }
 
-impl Default for RotatedDisplay2in13 {
+impl Default for Frame {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl DrawTarget for RotatedDisplay2in13 {
-    type Color = BinaryColor;
-    type Error = core::convert::Infallible;
+impl DrawTarget for Frame {
+    type Color = Color;
+    type Error = Infallible;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
         I: IntoIterator<Item = embedded_graphics::Pixel<Self::Color>>,
     {
-        self.0.draw_iter(pixels)
+        for Pixel(point, color) in pixels {
+            self.draw_pixel(point, color)?;
+        }
+
+        Ok(())
     }
 }
 
-impl OriginDimensions for RotatedDisplay2in13 {
-    fn size(&self) -> embedded_graphics::prelude::Size {
-        Size::new(self.0.size().height, self.0.size().width)
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        match self.buffer.rotation() {
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => Size::new(HEIGHT, WIDTH),
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => Size::new(WIDTH, HEIGHT),
+        }
     }
 }
 
-impl Display for RotatedDisplay2in13 {
+impl Display for Frame {
     fn buffer(&self) -> &[u8] {
-        self.0.buffer()
+        self.buffer.buffer()
     }
 
     fn get_mut_buffer(&mut self) -> &mut [u8] {
-        self.0.get_mut_buffer()
+        self.buffer.get_mut_buffer()
     }
 
     fn set_rotation(&mut self, rotation: DisplayRotation) {
-        self.0.set_rotation(rotation)
+        self.buffer.set_rotation(rotation)
     }
 
     fn rotation(&self) -> DisplayRotation {
-        self.0.rotation()
+        self.buffer.rotation()
     }
 }
 
 pub fn get_epd_display(
-    busy: gpio::Gpio4<gpio::Input>,
-    rst: gpio::Gpio16<gpio::Output>,
-    dc: gpio::Gpio17<gpio::Output>,
-    cs: gpio::Gpio5<gpio::Output>,
-    sclk: gpio::Gpio18<gpio::Unknown>,
-    mosi: gpio::Gpio23<gpio::Unknown>,
+    busy: Busy,
+    rst: Rst,
+    dc: Dc,
+    cs: Cs,
+    sclk: Sclk,
+    mosi: Mosi,
     spi3: SPI3,
 ) -> Result<EpdDisplay> {
     let spi_pins = Pins {
         sclk,
         sdo: mosi,
         sdi: Option::<gpio::Gpio1<gpio::Input>>::None,
-        cs: Option::<gpio::Gpio5<gpio::Output>>::None,
+        cs: Option::<Cs>::None,
     };
 
     let spi_config = Config {
@@ -112,57 +454,12 @@ pub fn get_epd_display(
 
     let mut delay = delay::Ets;
 
-    let epd2in13: Edp2in13Display =
-        Epd2in13::new(&mut master, cs, busy, dc, rst, &mut delay).unwrap();
-
-    let display: Box<RotatedDisplay2in13> = Default::default();
-    //     epd2in13.clear_frame(&mut master, &mut delay)?;
-
-    //     epd2in13
-    //         .set_refresh(&mut master, &mut delay, RefreshLut::Quick)
-    //         .unwrap();
-    //     epd2in13.clear_frame(&mut master, &mut delay).unwrap();
-    // use embedded_hal::blocking::delay::DelayMs;
-    //     // a moving `Hello World!`
-    //     let limit = 10;
-    //     for i in 0..limit {
-    //         draw_text(&mut display, "  Hello World! ", 5 + i * 12, 50);
-
-    //         epd2in13
-    //             .update_and_display_frame(&mut master, display.buffer(), &mut delay)
-    //             .expect("display frame new graphics");
-
-    //         delay.delay_ms(5u8);
-    //     }
+    let driver: PanelDriver = Driver::new(&mut master, cs, busy, dc, rst, &mut delay).unwrap();
 
     Ok(EpdDisplay {
-        epd2in13,
+        driver,
         master,
-        display,
+        frame: Default::default(),
         delay,
     })
 }
-
-#[allow(unused)]
-fn draw_text(display: &mut RotatedDisplay2in13, text: &str, x: i32, y: i32) {
-    use embedded_graphics::{
-        mono_font::MonoTextStyleBuilder,
-        prelude::*,
-        text::{Baseline, Text, TextStyleBuilder},
-    };
-    use epd_waveshare::{
-        color::*,
-        epd2in13_v2::{Display2in13, Epd2in13},
-        graphics::DisplayRotation,
-        prelude::*,
-    };
-    let style = MonoTextStyleBuilder::new()
-        .font(&embedded_graphics::mono_font::ascii::FONT_6X10)
-        .text_color(White)
-        .background_color(Black)
-        .build();
-
-    let text_style = TextStyleBuilder::new().baseline(Baseline::Top).build();
-
-    let _ = Text::with_text_style(text, Point::new(x, y), style, text_style).draw(display);
-}
@@ -1,161 +1,326 @@
 //! Module that handles the drawing to the display.
 
+pub mod color;
 pub mod display;
 pub mod pages;
+pub mod refresh;
 mod style;
 mod views;
 
 use crate::{datetime, state::State};
 // use display::OledDisplay;
 use anyhow::Result;
-use display::EpdDisplay;
+use color::Color;
+use display::Panel;
 use embedded_graphics::{
     mono_font::{iso_8859_1::FONT_6X10, MonoTextStyle},
     pixelcolor::BinaryColor,
     prelude::*,
-    primitives::Rectangle,
+    primitives::{PrimitiveStyle, Rectangle},
 };
+use embedded_layout::prelude::*;
 use embedded_text::{
     alignment::{HorizontalAlignment, VerticalAlignment},
     style::TextBoxStyleBuilder,
     TextBox,
 };
-use epd_waveshare::{color::Color, prelude::*};
 use pages::Page;
+use refresh::{DisplayConfig, RefreshStrategy};
 use std::{
-    sync::{mpsc::Receiver, Arc, Mutex},
-    time::Duration,
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
+use views::battery_icon::BatteryIcon;
 
-pub fn draw_pages(
-    display: &mut EpdDisplay,
+const STATUS_BAR_HEIGHT: u32 = 12;
+
+/// Requests to the display thread.
+pub enum DisplayRequest {
+    /// Redraw the current page.
+    Update,
+    /// Redraw the current page and confirm once the panel is done.
+    UpdateConfirmed(Sender<()>),
+    /// Confirm once all earlier requests are done without redrawing. Stores
+    /// the shown frame for the next wake up.
+    Flush(Sender<()>),
+}
+
+pub fn draw_pages<P: Panel>(
+    display: &mut P,
     state: Arc<Mutex<State>>,
-    update_page_rx: Receiver<()>,
+    update_page_rx: Receiver<DisplayRequest>,
 ) -> Result<()> {
+    let mut refresh_strategy = RefreshStrategy::new();
+    let mut last_redraw = Instant::now();
+    // The frame is blank until the first page is drawn, so the clock must not
+    // be drawn onto it before.
+    let mut page_drawn = false;
+
     loop {
-        let _ = update_page_rx.recv_timeout(Duration::from_secs(1800));
-
-        display
-            .epd2in13
-            .wake_up(&mut display.master, &mut display.delay)?;
-        display.display.clear_buffer(Color::White);
-        // display.epd2in13.clear_frame(&mut display.master, &mut display.delay)?;
-        {
-            let state = state.lock().unwrap();
-            let page = &state.page;
-            // let target = display.display.as_mut();
-
-            // let style = PrimitiveStyleBuilder::new()
-            //     .stroke_color(BinaryColor::On)
-            //     .stroke_width(1)
-            //     .fill_color(BinaryColor::Off)
-            //     .build();
-
-            let height = 12;
-            // Line::new(
-            //     Point::new(0, height),
-            //     Point::new(display.display.bounding_box().size.width as _, height),
-            // )
-            // .into_styled(style)
-            // .draw(display.display.as_mut())?;
-
-            let status_bar_area = Rectangle::new(
-                Point::zero(),
-                Size::new(display.display.bounding_box().size.width, height as _),
-            );
-
-            let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
-            let text_box_left_style = TextBoxStyleBuilder::new()
-                .alignment(HorizontalAlignment::Left)
-                .vertical_alignment(VerticalAlignment::Middle)
-                .build();
-            let text_box_center_style = TextBoxStyleBuilder::new()
-                .alignment(HorizontalAlignment::Center)
-                .vertical_alignment(VerticalAlignment::Middle)
-                .build();
-            let text_box_right_style = TextBoxStyleBuilder::new()
-                .alignment(HorizontalAlignment::Right)
-                .vertical_alignment(VerticalAlignment::Middle)
-                .build();
-
-            if let Ok(datetime) = datetime::get_datetime() {
-                let date_format = time::format_description::parse("[day].[month].[year]")
-                    .expect("Invalid format.");
-
-                let time_format =
-                    time::format_description::parse("[hour]:[minute]").expect("Invalid format.");
-
-                let date = datetime
-                    .format(&date_format)
-                    .expect("Could not format time.");
-                let time = datetime
-                    .format(&time_format)
-                    .expect("Could not format time.");
-
-                let percentage = {
-                    // The max value is around 2700.
-                    // The min value is around 1750.
-                    // Lets use a linear conversion to percentage.
-                    // It's incorrect but it's simple.
-
-                    let mut percentage = (0.105 * state.battery as f32 - 184.21) as i16;
-                    
-                    if percentage > 100 {
-                        percentage = 100;
-                    } else if percentage < 0 {
-                        percentage = 0;
-                    }
-
-                    percentage
-                };
-
-                let date_battery_string = format!("{}  {}%", &date, percentage);
-                let date_text = TextBox::with_textbox_style(
-                    &date_battery_string,
-                    status_bar_area,
-                    text_style,
-                    text_box_left_style,
-                );
-
-                let time_text = TextBox::with_textbox_style(
-                    &time,
-                    status_bar_area,
-                    text_style,
-                    text_box_center_style,
-                );
-
-                date_text.draw(display.display.as_mut())?;
-                time_text.draw(display.display.as_mut())?;
+        // Redraw regularly even without new data to keep the page up to date.
+        let refresh_interval = state.lock().unwrap().schedule.refresh_interval();
+        let until_redraw = refresh_interval
+            .checked_sub(last_redraw.elapsed())
+            .unwrap_or_default();
+
+        // Update the clock at the start of every minute in between.
+        let timeout = match datetime::get_local_time() {
+            Some(now) => until_redraw.min(Duration::from_secs(60 - now.second() as u64)),
+            None => until_redraw,
+        };
+
+        let mut clock = false;
+        let mut redraw = false;
+        let mut flush = false;
+        let mut confirmations = Vec::new();
+        let mut handle_request = |request| match request {
+            DisplayRequest::Update => redraw = true,
+            DisplayRequest::UpdateConfirmed(done) => {
+                redraw = true;
+                confirmations.push(done);
+            }
+            DisplayRequest::Flush(done) => {
+                flush = true;
+                confirmations.push(done);
             }
+        };
 
-            let location_text = TextBox::with_textbox_style(
-                &state.location,
-                status_bar_area,
-                text_style,
-                text_box_right_style,
-            );
-
-            location_text.draw(display.display.as_mut())?;
-
-            let page_area = Rectangle::new(
-                Point::new(0, height),
-                Size::new(
-                    display.display.bounding_box().size.width,
-                    display.display.bounding_box().size.height - height as u32,
-                ),
-            );
-            let mut page_draw_target = display.display.cropped(&page_area);
-            page.draw(&mut page_draw_target, &state).unwrap();
+        match update_page_rx.recv_timeout(timeout) {
+            Ok(request) => handle_request(request),
+            Err(_) if last_redraw.elapsed() >= refresh_interval => {
+                handle_request(DisplayRequest::Update)
+            }
+            Err(_) => clock = true,
         }
 
-        display.epd2in13.update_and_display_frame(
-            &mut display.master,
-            display.display.buffer(),
-            &mut display.delay,
-        )?;
+        // Combine all pending requests into a single redraw.
+        for request in update_page_rx.try_iter() {
+            handle_request(request);
+        }
+
+        if redraw {
+            draw_page(display, &state, &mut refresh_strategy)?;
+            last_redraw = Instant::now();
+            page_drawn = true;
+        } else if clock && page_drawn {
+            draw_clock(display, &state, &mut refresh_strategy)?;
+        }
+
+        // The device goes to sleep after a flush.
+        if flush {
+            refresh_strategy.save();
+        }
+
+        for done in confirmations {
+            done.send(()).ok();
+        }
+    }
+}
+
+/// Draws the status bar and the current page and waits until the panel is updated.
+fn draw_page<P: Panel>(
+    display: &mut P,
+    state: &Mutex<State>,
+    refresh_strategy: &mut RefreshStrategy,
+) -> Result<()> {
+    display.wake_up()?;
+
+    let display_config = {
+        let state = state.lock().unwrap();
+        let page = &state.page;
+        let display_config = effective_config(display, &state);
+
+        display.set_orientation(display_config.rotation, display_config.inverted);
+        display.clear();
+
+        draw_status_bar(&mut display.frame().color_converted(), &state)?;
+
+        let size = display.size();
+        let page_area = Rectangle::new(
+            Point::new(0, STATUS_BAR_HEIGHT as _),
+            Size::new(size.width, size.height.saturating_sub(STATUS_BAR_HEIGHT)),
+        );
+        let mut page_draw_target = display.frame().cropped(&page_area);
+        page.draw(&mut page_draw_target, &state).unwrap();
+
+        display_config
+    };
+
+    let base = refresh_strategy.quick_refresh_base(&display_config, display.buffer().len());
+    let quick = base.is_some();
+
+    match base {
+        Some(base) => display.show_quick(base)?,
+        None => display.show()?,
+    }
+    refresh_strategy.frame_shown(display.buffer(), quick);
+
+    display.sleep()?;
+
+    Ok(())
+}
+
+/// Redraws only the status bar and updates its area on the panel with a quick
+/// refresh. Falls back to a redraw of the whole page if the shown frame is
+/// unknown.
+fn draw_clock<P: Panel>(
+    display: &mut P,
+    state: &Mutex<State>,
+    refresh_strategy: &mut RefreshStrategy,
+) -> Result<()> {
+    let display_config = effective_config(display, &state.lock().unwrap());
+
+    // A full refresh every minute would flash the whole panel.
+    if !display_config.quick_refresh {
+        return Ok(());
+    }
+
+    let frame_size = display.buffer().len();
+    // The clock is not counted towards the next full refresh, that is left to
+    // the page updates.
+    let base = match refresh_strategy.shown_frame(frame_size) {
+        Some(base) => base.to_vec(),
+        None => return draw_page(display, state, refresh_strategy),
+    };
+
+    let status_bar_area = Rectangle::new(
+        Point::zero(),
+        Size::new(display.size().width, STATUS_BAR_HEIGHT),
+    );
+    {
+        let state = state.lock().unwrap();
+        let target = display.frame();
 
-        display
-            .epd2in13
-            .sleep(&mut display.master, &mut display.delay)?;
+        status_bar_area
+            .into_styled(PrimitiveStyle::with_fill(Color::White))
+            .draw(target)?;
+        draw_status_bar(&mut target.color_converted(), &state)?;
     }
+
+    display.wake_up()?;
+    display.show_quick_area(&base, status_bar_area)?;
+    refresh_strategy.area_shown(display.buffer());
+    display.sleep()?;
+
+    Ok(())
+}
+
+/// The configured refresh behaviour, limited to what the panel supports.
+fn effective_config<P: Panel>(display: &P, state: &State) -> DisplayConfig {
+    let mut config = state.display_config.clone();
+    config.quick_refresh &= display.supports_quick_refresh();
+
+    config
+}
+
+/// Pages use a narrower layout if the display is rotated to portrait.
+fn is_portrait(size: Size) -> bool {
+    size.width < size.height
+}
+
+/// Date, battery and time on the left and the location on the right. In
+/// portrait there is only room for the time and the battery.
+fn draw_status_bar<D>(target: &mut D, state: &State) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let status_bar_area = Rectangle::new(
+        Point::zero(),
+        Size::new(target.bounding_box().size.width, STATUS_BAR_HEIGHT),
+    );
+
+    let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let text_box_left_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Left)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+    let text_box_center_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Center)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+    let text_box_right_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Right)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+
+    if is_portrait(status_bar_area.size) {
+        return draw_narrow_status_bar(target, state, status_bar_area);
+    }
+
+    if let Ok(datetime) = datetime::get_datetime() {
+        let date_format =
+            time::format_description::parse("[day].[month].[year]").expect("Invalid format.");
+
+        let time_format =
+            time::format_description::parse("[hour]:[minute]").expect("Invalid format.");
+
+        let date = datetime
+            .format(&date_format)
+            .expect("Could not format time.");
+        let time = datetime
+            .format(&time_format)
+            .expect("Could not format time.");
+
+        let date_text =
+            TextBox::with_textbox_style(&date, status_bar_area, text_style, text_box_left_style);
+
+        let time_text =
+            TextBox::with_textbox_style(&time, status_bar_area, text_style, text_box_center_style);
+
+        date_text.draw(target)?;
+        time_text.draw(target)?;
+    }
+
+    // Right of the date which is 10 characters wide.
+    BatteryIcon::new(state.battery)
+        .align_to(&status_bar_area, horizontal::Left, vertical::Center)
+        .translate(Point::new(66, 0))
+        .draw(target)?;
+
+    let location_text = TextBox::with_textbox_style(
+        &state.location,
+        status_bar_area,
+        text_style,
+        text_box_right_style,
+    );
+
+    location_text.draw(target)?;
+
+    Ok(())
+}
+
+/// Time on the left and the battery on the right.
+fn draw_narrow_status_bar<D>(
+    target: &mut D,
+    state: &State,
+    status_bar_area: Rectangle,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let text_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let text_box_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Left)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+
+    if let Ok(datetime) = datetime::get_datetime() {
+        let time_format =
+            time::format_description::parse("[hour]:[minute]").expect("Invalid format.");
+        let time = datetime
+            .format(&time_format)
+            .expect("Could not format time.");
+
+        TextBox::with_textbox_style(&time, status_bar_area, text_style, text_box_style)
+            .draw(target)?;
+    }
+
+    BatteryIcon::new(state.battery)
+        .align_to(&status_bar_area, horizontal::Right, vertical::Center)
+        .draw(target)?;
+
+    Ok(())
 }
@@ -1,8 +1,9 @@
 use crate::{
     datetime, definitions,
     graphics::{
-        style,
-        views::{feed_group::FeedGroup, forecast_row::ForecastRow},
+        color::Color,
+        is_portrait, style,
+        views::{feed_group::FeedGroup, forecast_row::ForecastRow, qr_code::QrCode},
     },
     state::State,
     storage::{ReadFile, BASE_DIR},
@@ -48,14 +49,56 @@ pub struct ConfigPage;
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct StockPage;
 
+/// Battery state and history.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SystemPage;
+
+/// Shown before the device shuts down because of an empty battery.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LowBatteryPage;
+
 #[enum_dispatch(Page)]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PageType {
     FeedPage,
     WeatherPage,
     StockPage,
+    SystemPage,
     ExamplePage,
     ConfigPage,
+    LowBatteryPage,
+}
+
+impl PageType {
+    /// Name of the page as used by the web API.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PageType::FeedPage(_) => "feed",
+            PageType::WeatherPage(WeatherPage(WeatherPageType::Hourly)) => "weather_hourly",
+            PageType::WeatherPage(WeatherPage(WeatherPageType::Daily)) => "weather_daily",
+            PageType::StockPage(_) => "stock",
+            PageType::SystemPage(_) => "system",
+            PageType::ExamplePage(_) => "example",
+            PageType::ConfigPage(_) => "config",
+            PageType::LowBatteryPage(_) => "low_battery",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let page = match name {
+            "feed" => FeedPage.into(),
+            "weather_hourly" => WeatherPage(WeatherPageType::Hourly).into(),
+            "weather_daily" => WeatherPage(WeatherPageType::Daily).into(),
+            "stock" => StockPage.into(),
+            "system" => SystemPage.into(),
+            "example" => ExamplePage.into(),
+            "config" => ConfigPage.into(),
+            "low_battery" => LowBatteryPage.into(),
+            _ => return None,
+        };
+
+        Some(page)
+    }
 }
 
 impl Default for PageType {
@@ -64,12 +107,13 @@ impl Default for PageType {
     }
 }
 
+/// Pages draw with `Color`. Views that only know black and white are drawn on
+/// `target.color_converted::<BinaryColor>()`.
 #[enum_dispatch]
 pub trait Page: Send + Sync {
     fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>;
+        D: DrawTarget<Color = Color> + Dimensions;
 
     fn next_page(&self) -> PageType;
 }
@@ -77,8 +121,7 @@ pub trait Page: Send + Sync {
 impl Page for FeedPage {
     fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>,
+        D: DrawTarget<Color = Color> + Dimensions,
     {
         let mut groups = Vec::new();
 
@@ -117,8 +160,7 @@ impl Page for FeedPage {
 impl Page for StockPage {
     fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>,
+        D: DrawTarget<Color = Color> + Dimensions,
     {
         use embedded_plots::{axis::Scale, curve::Curve, single_plot::SinglePlot};
 
@@ -127,11 +169,12 @@ impl Page for StockPage {
             const BORDER: u32 = 12;
 
             let bounds = &target.bounding_box();
+            let mono_target = &mut target.color_converted::<BinaryColor>();
 
             let top_left = Point::new(BORDER as _, BORDER as _) + Point::new(5, 0);
             let bottom_right = Point::new(
-                (bounds.size.width - BORDER) as _,
-                (bounds.size.height - BORDER) as _,
+                bounds.size.width.saturating_sub(BORDER) as _,
+                bounds.size.height.saturating_sub(BORDER) as _,
             );
 
             let plot = SinglePlot::new(&curve, Scale::RangeFraction(5), Scale::RangeFraction(3))
@@ -146,8 +189,107 @@ impl Page for StockPage {
             .align_to(&target.bounding_box(), horizontal::Center, vertical::Top)
             .translate(Point::new(-10, 10));
 
-            plot.draw(target)?;
-            symbol_text.draw(target)?;
+            plot.draw(mono_target)?;
+            symbol_text.draw(mono_target)?;
+
+            // A loss over the shown period is highlighted.
+            if let (Some(first), Some(last)) = (stock_data.first(), stock_data.last()) {
+                if first.y > 0 {
+                    let change = (last.y - first.y) as f32 * 100.0 / first.y as f32;
+                    let change_text = format!("{:+.1}%", change);
+
+                    if change < 0.0 {
+                        Text::new(&change_text, Point::zero(), style::highlight_text())
+                            .align_to(bounds, horizontal::Right, vertical::Top)
+                            .translate(Point::new(-(BORDER as i32), 2))
+                            .draw(target)?;
+                    } else {
+                        Text::new(&change_text, Point::zero(), style::normal_text())
+                            .align_to(bounds, horizontal::Right, vertical::Top)
+                            .translate(Point::new(-(BORDER as i32), 2))
+                            .draw(mono_target)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next_page(&self) -> PageType {
+        SystemPage.into()
+    }
+}
+
+impl Page for SystemPage {
+    fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Color> + Dimensions,
+    {
+        use embedded_plots::{
+            axis::Scale,
+            curve::{Curve, PlotPoint},
+            single_plot::SinglePlot,
+        };
+
+        let target = &mut target.color_converted::<BinaryColor>();
+
+        let battery = if state.battery.external_power {
+            String::from("Akku: Externe Versorgung")
+        } else {
+            format!(
+                "Akku: {}.{:02} V ({}%)",
+                state.battery.voltage / 1000,
+                state.battery.voltage % 1000 / 10,
+                state.battery.percentage
+            )
+        };
+        let remaining = match state.battery_history.days_remaining() {
+            Some(days) => format!("Restlaufzeit: ca. {:.1} Tage", days),
+            None => String::from("Restlaufzeit: unbekannt"),
+        };
+
+        // The lines are wrapped in portrait, which leaves less room for the chart.
+        let bounds = target.bounding_box();
+        let text_height = if is_portrait(bounds.size) { 44 } else { 24 };
+        let text_area = Rectangle::new(
+            Point::new(4, 2),
+            Size::new(bounds.size.width.saturating_sub(8), text_height),
+        );
+
+        TextBox::new(
+            &format!("{}\n{}", battery, remaining),
+            text_area,
+            style::normal_text(),
+        )
+        .draw(target)?;
+
+        // Hours before the last sample against the voltage.
+        let samples = state.battery_history.samples();
+        if let Some(last) = samples.last() {
+            let points: Vec<_> = samples
+                .iter()
+                .map(|sample| PlotPoint {
+                    x: (sample.time as i32 - last.time as i32) / 3600,
+                    y: sample.voltage as i32,
+                })
+                .collect();
+
+            if points.len() >= 2 {
+                const BORDER: u32 = 12;
+
+                let top_left = Point::new(BORDER as i32 + 15, text_height as i32 + 6);
+                let bottom_right = Point::new(
+                    bounds.size.width.saturating_sub(BORDER) as _,
+                    bounds.size.height.saturating_sub(BORDER) as _,
+                );
+
+                let curve = Curve::from_data(&points);
+                SinglePlot::new(&curve, Scale::RangeFraction(4), Scale::RangeFraction(2))
+                    .into_drawable(top_left, bottom_right)
+                    .set_color(BinaryColor::On)
+                    .draw(target)?;
+            }
         }
 
         Ok(())
@@ -161,8 +303,7 @@ impl Page for StockPage {
 impl Page for WeatherPage {
     fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>,
+        D: DrawTarget<Color = Color> + Dimensions,
     {
         if let Some(ref report) = state.weather_controller.current() {
             let mut icon_file =
@@ -179,13 +320,34 @@ impl Page for WeatherPage {
 
             let text_style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
 
-            let forecast_row = ForecastRow::new(&state.weather_controller, self.0.into())
-                .align_to(&target.bounding_box(), horizontal::Left, vertical::Bottom)
-                .translate(Point::new(-1, 1));
+            let forecast_row = ForecastRow::new(
+                &state.weather_controller,
+                self.0.into(),
+                target.bounding_box().size.width,
+            )
+            .align_to(&target.bounding_box(), horizontal::Left, vertical::Bottom)
+            .translate(Point::new(-1, 1));
 
             let forecast_row_top_right = forecast_row.bounding_box().top_left
                 + Point::new(forecast_row.bounding_box().size.width as _, 0);
-            let current_layout_box = Rectangle::with_corners(Point::zero(), forecast_row_top_right);
+            let mut current_layout_box =
+                Rectangle::with_corners(Point::zero(), forecast_row_top_right);
+
+            // Weather warnings are shown above the current weather.
+            if let Some(alert) = state.weather_controller.alert() {
+                let alert_text = Text::new(alert, Point::zero(), style::highlight_text())
+                    .align_to(&current_layout_box, horizontal::Center, vertical::Top)
+                    .translate(Point::new(0, 2));
+                let alert_bottom = alert_text.bounding_box().bottom_right().unwrap_or_default();
+
+                current_layout_box = Rectangle::with_corners(
+                    Point::new(0, alert_bottom.y + 1),
+                    forecast_row_top_right,
+                );
+                alert_text.draw(target)?;
+            }
+
+            let target = &mut target.color_converted::<BinaryColor>();
 
             let description = Text::new(report.description, Point::zero(), text_style).align_to(
                 &forecast_row.bounding_box(),
@@ -204,6 +366,25 @@ impl Page for WeatherPage {
                 vertical::BottomToTop,
             );
 
+            forecast_row.draw(target)?;
+
+            // The temperature does not fit next to the icon.
+            if is_portrait(target.bounding_box().size) {
+                let description =
+                    Text::new(report.description, Point::zero(), style::normal_text());
+
+                return LinearLayout::vertical(
+                    Chain::new(weather_icon)
+                        .append(temperature_humidity)
+                        .append(description),
+                )
+                .with_alignment(horizontal::Center)
+                .with_spacing(FixedMargin(4))
+                .arrange()
+                .align_to(&current_layout_box, horizontal::Center, vertical::Center)
+                .draw(target);
+            }
+
             let icon_temp_hum_layout =
                 LinearLayout::horizontal(Chain::new(weather_icon).append(temperature_humidity))
                     .with_alignment(vertical::Center)
@@ -218,7 +399,6 @@ impl Page for WeatherPage {
                     .align_to(&current_layout_box, horizontal::Left, vertical::Center)
                     .translate(Point::new(8, 0));
 
-            forecast_row.draw(target)?;
             current_layout.draw(target)?;
         }
 
@@ -236,9 +416,10 @@ impl Page for WeatherPage {
 impl Page for ExamplePage {
     fn draw<D>(&self, target: &mut D, _: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>,
+        D: DrawTarget<Color = Color> + Dimensions,
     {
+        let target = &mut target.color_converted::<BinaryColor>();
+
         let fill = PrimitiveStyle::with_fill(BinaryColor::On);
 
         let y_offset = 10;
@@ -313,11 +494,12 @@ impl Page for ExamplePage {
 }
 
 impl Page for ConfigPage {
-    fn draw<D>(&self, target: &mut D, _: &State) -> Result<(), D::Error>
+    fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
     where
-        D: DrawTarget<Color = BinaryColor> + Dimensions,
-        D::Color: From<BinaryColor>,
+        D: DrawTarget<Color = Color> + Dimensions,
     {
+        let target = &mut target.color_converted::<BinaryColor>();
+
         target
             .bounding_box()
             .into_styled(style::border_stroke())
@@ -328,15 +510,105 @@ impl Page for ConfigPage {
             .vertical_alignment(embedded_text::alignment::VerticalAlignment::Middle)
             .build();
 
-        TextBox::with_textbox_style(
-            &format!(
-                "Setup Mode\n\nSSID: {}\nPassword: {}\nIP: 192.168.71.1",
-                definitions::AP_SSID,
-                definitions::AP_PASSWORD
-            ),
-            target.bounding_box(),
+        // Explain why the setup mode was entered without user interaction.
+        let reason = match state.setup_reason {
+            Some(ref reason) => format!("WLAN-Fehler: {}\n", reason),
+            None => String::new(),
+        };
+
+        // First help to join the access point, then to open the settings.
+        let settings_url = format!("http://{}/", definitions::AP_IP);
+        let (text, qr_text) = if state.access_point_clients > 0 {
+            (
+                format!("Setup Mode\n\nVerbunden!\nEinstellungen:\n{}", settings_url),
+                settings_url,
+            )
+        } else {
+            (
+                format!(
+                    "Setup Mode\n{}\nSSID: {}\nPassword: {}\nIP: {}",
+                    reason,
+                    state.access_point.ssid,
+                    state.access_point.password,
+                    definitions::AP_IP
+                ),
+                state.access_point.join_uri(),
+            )
+        };
+
+        let bounds = target.bounding_box();
+        let mut text_area = bounds;
+
+        // The code is placed next to the text in landscape and below it in portrait.
+        if is_portrait(bounds.size) {
+            if let Some(qr_code) = QrCode::new(&qr_text, bounds.size.width.saturating_sub(8)) {
+                let qr_code = qr_code
+                    .align_to(&bounds, horizontal::Center, vertical::Bottom)
+                    .translate(Point::new(0, -4));
+                text_area.size.height = text_area
+                    .size
+                    .height
+                    .saturating_sub(qr_code.size().height + 4);
+
+                qr_code.draw(target)?;
+            }
+        } else if let Some(qr_code) = QrCode::new(&qr_text, bounds.size.height.saturating_sub(8)) {
+            let qr_code = qr_code
+                .align_to(&bounds, horizontal::Right, vertical::Center)
+                .translate(Point::new(-4, 0));
+            text_area.size.width = text_area
+                .size
+                .width
+                .saturating_sub(qr_code.size().width + 4);
+
+            qr_code.draw(target)?;
+        }
+
+        TextBox::with_textbox_style(&text, text_area, style::normal_text(), textbox_style)
+            .draw(target)?;
+
+        Ok(())
+    }
+
+    fn next_page(&self) -> PageType {
+        ConfigPage.into()
+    }
+}
+
+impl Page for LowBatteryPage {
+    fn draw<D>(&self, target: &mut D, state: &State) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Color> + Dimensions,
+    {
+        let target = &mut target.color_converted::<BinaryColor>();
+
+        target
+            .bounding_box()
+            .into_styled(style::border_stroke())
+            .draw(target)?;
+
+        let title_style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+
+        // The title is too wide for a single line in portrait.
+        let (title, title_offset) = if is_portrait(target.bounding_box().size) {
+            ("Bitte\naufladen", Point::new(0, 28))
+        } else {
+            ("Bitte aufladen", Point::new(0, 8))
+        };
+
+        Text::with_alignment(
+            title,
+            target.bounding_box().center() - title_offset,
+            title_style,
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        Text::with_alignment(
+            &format!("Akku: {}%", state.battery.percentage),
+            target.bounding_box().center() + Point::new(0, 16),
             style::normal_text(),
-            textbox_style,
+            Alignment::Center,
         )
         .draw(target)?;
 
@@ -344,6 +616,6 @@ impl Page for ConfigPage {
     }
 
     fn next_page(&self) -> PageType {
-        ConfigPage.into()
+        LowBatteryPage.into()
     }
 }
@@ -0,0 +1,130 @@
+//! Chooses between a quick partial refresh and a full refresh of the panel.
+
+use crate::storage::BASE_DIR;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Whether the panel was fully refreshed since it was powered on. Kept in the
+/// RTC memory, as the panel keeps its image during deep sleep.
+#[link_section = ".rtc.data"]
+static mut PANEL_INITIALIZED: bool = false;
+/// Quick refreshes since the last full refresh.
+#[link_section = ".rtc.data"]
+static mut QUICK_REFRESHES: u32 = 0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DisplayConfig {
+    /// Use the quick refresh which does not flash the whole panel.
+    pub quick_refresh: bool,
+    /// Every n-th update is a full refresh to remove the ghosting of the
+    /// quick refreshes.
+    pub full_refresh_interval: u32,
+    /// Rotation of the content in degrees clockwise (0, 90, 180 or 270).
+    #[serde(default)]
+    pub rotation: u16,
+    /// Draw white on black.
+    #[serde(default)]
+    pub inverted: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            quick_refresh: true,
+            full_refresh_interval: 10,
+            rotation: 0,
+            inverted: false,
+        }
+    }
+}
+
+/// The FAT partition only supports 8.3 file names.
+fn frame_path() -> String {
+    format!("{}/cache/frame.dat", BASE_DIR)
+}
+
+/// A quick refresh needs the image that is currently shown as its base. It
+/// is stored on the flash so it survives the deep sleep.
+pub struct RefreshStrategy {
+    shown_frame: Option<Vec<u8>>,
+    /// The shown frame changed since it was last stored.
+    unsaved: bool,
+}
+
+impl RefreshStrategy {
+    pub fn new() -> Self {
+        // After a power on the panel may show anything.
+        let shown_frame = if unsafe { PANEL_INITIALIZED } {
+            fs::read(frame_path()).ok()
+        } else {
+            None
+        };
+
+        Self {
+            shown_frame,
+            unsaved: false,
+        }
+    }
+
+    /// Returns the base for a quick refresh of a frame with the given size or
+    /// `None` if a full refresh is due.
+    pub fn quick_refresh_base(&self, config: &DisplayConfig, frame_size: usize) -> Option<&[u8]> {
+        let quick_refreshes = unsafe { QUICK_REFRESHES };
+
+        if !config.quick_refresh || quick_refreshes + 1 >= config.full_refresh_interval {
+            return None;
+        }
+
+        self.shown_frame(frame_size)
+    }
+
+    /// The frame that is currently shown if it has the given size.
+    pub fn shown_frame(&self, frame_size: usize) -> Option<&[u8]> {
+        self.shown_frame
+            .as_deref()
+            .filter(|frame| frame.len() == frame_size)
+    }
+
+    /// Remembers the frame of a page that is now shown on the panel and
+    /// stores it.
+    pub fn frame_shown(&mut self, frame: &[u8], quick: bool) {
+        unsafe {
+            PANEL_INITIALIZED = true;
+            QUICK_REFRESHES = if quick { QUICK_REFRESHES + 1 } else { 0 };
+        }
+
+        self.shown_frame = Some(frame.to_vec());
+        self.unsaved = true;
+        self.save();
+    }
+
+    /// Remembers the frame after a small area was updated. This neither
+    /// counts towards the next full refresh nor writes to the flash, as it
+    /// happens every minute. Call [`Self::save`] before going to sleep.
+    pub fn area_shown(&mut self, frame: &[u8]) {
+        self.shown_frame = Some(frame.to_vec());
+        self.unsaved = true;
+    }
+
+    /// Stores the shown frame if it changed since it was last stored.
+    pub fn save(&mut self) {
+        let frame = match &self.shown_frame {
+            Some(frame) if self.unsaved => frame,
+            _ => return,
+        };
+
+        if let Err(e) = fs::create_dir_all(format!("{}/cache", BASE_DIR))
+            .and_then(|_| fs::write(frame_path(), frame))
+        {
+            log::warn!("Could not store the shown frame: {}", e);
+        }
+
+        self.unsaved = false;
+    }
+}
+
+impl Default for RefreshStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
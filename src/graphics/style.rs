@@ -1,3 +1,4 @@
+use super::color::{Color, CHROMATIC};
 use embedded_graphics::{
     mono_font::{iso_8859_1::*, MonoTextStyle, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
@@ -27,3 +28,18 @@ pub fn normal_text() -> MonoTextStyle<'static, BinaryColor> {
         .background_color(BinaryColor::Off)
         .build()
 }
+
+/// Red text on three color panels and inverted text on monochrome panels.
+pub fn highlight_text() -> MonoTextStyle<'static, Color> {
+    let (text_color, background_color) = if CHROMATIC {
+        (Color::Red, Color::White)
+    } else {
+        (Color::White, Color::Black)
+    };
+
+    MonoTextStyleBuilder::new()
+        .font(&FONT_6X10)
+        .text_color(text_color)
+        .background_color(background_color)
+        .build()
+}
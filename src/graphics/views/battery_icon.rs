@@ -0,0 +1,80 @@
+use crate::battery::BatteryStatus;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::BinaryColor,
+    prelude::{Dimensions, Point, Primitive, Size},
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    Drawable,
+};
+use embedded_layout::prelude::*;
+
+/// Battery symbol that is filled according to the charge. A lightning bolt
+/// is shown instead of the filling if the board is powered externally.
+pub struct BatteryIcon {
+    status: BatteryStatus,
+    bounds: Rectangle,
+}
+
+impl BatteryIcon {
+    pub fn new(status: BatteryStatus) -> Self {
+        Self {
+            status,
+            bounds: Rectangle::new(Point::zero(), Size::new(16, 8)),
+        }
+    }
+}
+
+impl View for BatteryIcon {
+    fn translate_impl(&mut self, by: Point) {
+        self.bounds.translate_mut(by);
+    }
+
+    fn bounds(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl Dimensions for BatteryIcon {
+    fn bounding_box(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl Drawable for BatteryIcon {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        let origin = self.bounds.top_left;
+        let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+
+        // Body and the positive terminal on the right side.
+        Rectangle::new(origin, Size::new(14, 8))
+            .into_styled(stroke)
+            .draw(target)?;
+        Rectangle::new(origin + Point::new(14, 2), Size::new(2, 4))
+            .into_styled(fill)
+            .draw(target)?;
+
+        if self.status.external_power {
+            Line::new(origin + Point::new(8, 1), origin + Point::new(5, 4))
+                .into_styled(stroke)
+                .draw(target)?;
+            Line::new(origin + Point::new(5, 4), origin + Point::new(9, 4))
+                .into_styled(stroke)
+                .draw(target)?;
+            Line::new(origin + Point::new(9, 4), origin + Point::new(6, 7))
+                .into_styled(stroke)
+                .draw(target)?;
+        } else {
+            let width = (10 * self.status.percentage as u32 + 50) / 100;
+
+            Rectangle::new(origin + Point::new(2, 2), Size::new(width, 4))
+                .into_styled(fill)
+                .draw(target)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
-use crate::feed::Feed;
+use crate::{
+    feed::Feed,
+    graphics::{color::Color, style},
+};
 use embedded_graphics::{
     mono_font::{iso_8859_1::*, MonoTextStyle},
-    pixelcolor::BinaryColor,
     prelude::*,
     primitives::Rectangle,
 };
@@ -18,10 +20,17 @@ pub struct FeedGroup<'a, C: PixelColor> {
     pub headline3: TextBox<'a, MonoTextStyle<'static, C>>,
 }
 
-impl<'a> FeedGroup<'a, BinaryColor> {
+impl<'a> FeedGroup<'a, Color> {
+    /// Headlines that are new since the last refresh are highlighted.
     pub fn new(feed: &'a Feed, target_bounds: Rectangle) -> Self {
-        let title_style = MonoTextStyle::new(&FONT_6X13_BOLD, BinaryColor::On);
-        let headline_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        let title_style = MonoTextStyle::new(&FONT_6X13_BOLD, Color::Black);
+        let headline_style = |headline| {
+            if feed.is_new(headline) {
+                style::highlight_text()
+            } else {
+                MonoTextStyle::new(&FONT_6X10, Color::Black)
+            }
+        };
 
         let textbox_style = TextBoxStyleBuilder::new()
             .height_mode(HeightMode::FitToText)
@@ -35,25 +44,25 @@ impl<'a> FeedGroup<'a, BinaryColor> {
             headline0: TextBox::with_textbox_style(
                 feed.headlines.get(0).unwrap(),
                 bounds,
-                headline_style,
+                headline_style(0),
                 textbox_style,
             ),
             headline1: TextBox::with_textbox_style(
                 feed.headlines.get(1).unwrap(),
                 bounds,
-                headline_style,
+                headline_style(1),
                 textbox_style,
             ),
             headline2: TextBox::with_textbox_style(
                 feed.headlines.get(2).unwrap(),
                 bounds,
-                headline_style,
+                headline_style(2),
                 textbox_style,
             ),
             headline3: TextBox::with_textbox_style(
                 feed.headlines.get(3).unwrap(),
                 bounds,
-                headline_style,
+                headline_style(3),
                 textbox_style,
             ),
         }
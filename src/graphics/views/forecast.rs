@@ -19,6 +19,9 @@ use embedded_layout::{
 use std::fs::File;
 use tinytga::DynamicTga;
 
+/// The borders of neighbouring forecasts in a row overlap by one pixel.
+pub const FORECAST_SIZE: Size = Size::new(42, 52);
+
 #[derive(Clone)]
 pub struct Forecast<'a> {
     icon_code: &'a str,
@@ -33,7 +36,7 @@ impl<'a> Forecast<'a> {
             icon_code: icon,
             datetime,
             temperature,
-            bounds: Rectangle::new(Point::zero(), Size::new(42, 52)),
+            bounds: Rectangle::new(Point::zero(), FORECAST_SIZE),
         }
     }
 }
@@ -65,17 +68,29 @@ impl<'a> Drawable for Forecast<'a> {
         // Create a 1px border
         let border = self.bounding_box().into_styled(border_style);
 
-        let mut icon_file =
-            File::open(format!("{}/weather/small/{}.tga", BASE_DIR, self.icon_code)).unwrap();
-        let raw_bytes = icon_file.raw_bytes();
-        let tga_image = DynamicTga::from_slice(&raw_bytes).unwrap();
-        let image = Image::new(&tga_image, Point::zero());
-
         let datetime_text = Text::new(&self.datetime, Point::zero(), style::normal_text());
 
         let temp = &format!("{:.1}°C", self.temperature);
         let temperature_text = Text::new(temp, Point::zero(), style::normal_text());
 
+        border.draw(target)?;
+
+        // Leave out the icon if there is none for the code.
+        let icon = File::open(format!("{}/weather/small/{}.tga", BASE_DIR, self.icon_code))
+            .map(|mut file| file.raw_bytes());
+        let tga_image = match icon.as_deref().map(DynamicTga::from_slice) {
+            Ok(Ok(image)) => image,
+            _ => {
+                return LinearLayout::vertical(Chain::new(datetime_text).append(temperature_text))
+                    .with_spacing(DistributeFill(self.bounding_box().size.height - 4))
+                    .with_alignment(horizontal::Center)
+                    .arrange()
+                    .align_to(&border, horizontal::Center, vertical::Center)
+                    .draw(target);
+            }
+        };
+        let image = Image::new(&tga_image, Point::zero());
+
         let layout = LinearLayout::vertical(
             Chain::new(datetime_text)
                 .append(image)
@@ -86,8 +101,6 @@ impl<'a> Drawable for Forecast<'a> {
         .arrange()
         .align_to(&border, horizontal::Center, vertical::Center);
 
-        // Draw views
-        border.draw(target)?;
         layout.draw(target)?;
 
         Ok(())
@@ -1,28 +1,23 @@
 use crate::{
     datetime,
-    graphics::{pages::WeatherPageType, views::forecast::Forecast},
+    graphics::{
+        pages::WeatherPageType,
+        views::forecast::{Forecast, FORECAST_SIZE},
+    },
     weather::{WeatherController, WeatherReport},
 };
 use embedded_graphics::{
     draw_target::DrawTarget, pixelcolor::BinaryColor, prelude::*, primitives::Rectangle,
 };
-use embedded_layout::{
-    align::vertical::Bottom,
-    layout::linear::{FixedMargin, Horizontal, LinearLayout},
-    prelude::*,
-};
+use embedded_layout::prelude::*;
 
-type LayoutOrientation = Horizontal<Bottom, FixedMargin>;
-type Layout<'a> = LinearLayout<
-    LayoutOrientation,
-    Link<
-        Forecast<'a>,
-        Link<Forecast<'a>, Link<Forecast<'a>, Link<Forecast<'a>, Chain<Forecast<'a>>>>>,
-    >,
->;
+/// The forecast of the first day is the current weather.
+const MAX_DAILY_FORECASTS: usize = 7;
+const MAX_HOURLY_FORECASTS: usize = 12;
 
 pub struct ForecastRow<'a> {
-    layout: Layout<'a>,
+    forecasts: Vec<Forecast<'a>>,
+    bounds: Rectangle,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -41,43 +36,56 @@ impl From<WeatherPageType> for ForecastType {
 }
 
 impl<'a> ForecastRow<'a> {
-    pub fn new(controller: &'a WeatherController, forecast_type: ForecastType) -> Self {
-        let forecast_widgets: [Forecast; 5] = array_init::array_init(|i| {
-            let i = i + 1; // First index is same as current.
-
-            let WeatherReport { dt, icon, temp, .. } = match forecast_type {
-                ForecastType::Hourly => controller.hourly(i).unwrap_or_default(),
-                ForecastType::Daily => controller.daily(i).unwrap_or_default(),
-            };
-
-            let format = match forecast_type {
-                ForecastType::Hourly => time::format_description::parse("[hour]:00").unwrap(),
-                ForecastType::Daily => time::format_description::parse("[day].[month]").unwrap(),
-            };
-
-            let datetime = datetime::get_datetime_from_unix(dt as _).unwrap();
-            let time = datetime.format(&format).expect("Could not format time.");
-
-            Forecast::new(icon, time, temp)
-        });
-
-        let layout = LinearLayout::horizontal(
-            Chain::new(forecast_widgets[0].clone())
-                .append(forecast_widgets[1].clone())
-                .append(forecast_widgets[2].clone())
-                .append(forecast_widgets[3].clone())
-                .append(forecast_widgets[4].clone()),
-        )
-        .with_spacing(FixedMargin(-1))
-        .arrange();
-
-        Self { layout }
+    /// Shows as many forecasts as fit into the given width.
+    pub fn new(controller: &'a WeatherController, forecast_type: ForecastType, width: u32) -> Self {
+        let max_count = match forecast_type {
+            ForecastType::Hourly => MAX_HOURLY_FORECASTS,
+            ForecastType::Daily => MAX_DAILY_FORECASTS,
+        };
+        let count =
+            ((width.saturating_sub(1) / (FORECAST_SIZE.width - 1)) as usize).clamp(1, max_count);
+
+        let format = match forecast_type {
+            ForecastType::Hourly => time::format_description::parse("[hour]:00").unwrap(),
+            ForecastType::Daily => time::format_description::parse("[day].[month]").unwrap(),
+        };
+
+        // First index is same as current. Only the forecasts that were
+        // fetched are shown, even if more would fit.
+        let forecasts: Vec<_> = (1..=count)
+            .filter_map(|i| match forecast_type {
+                ForecastType::Hourly => controller.hourly(i),
+                ForecastType::Daily => controller.daily(i),
+            })
+            .enumerate()
+            .map(|(i, WeatherReport { dt, icon, temp, .. })| {
+                let datetime = datetime::get_datetime_from_unix(dt as _).unwrap();
+                let time = datetime.format(&format).expect("Could not format time.");
+
+                let offset = i as i32 * (FORECAST_SIZE.width - 1) as i32;
+                Forecast::new(icon, time, temp).translate(Point::new(offset, 0))
+            })
+            .collect();
+        let count = forecasts.len();
+
+        let bounds = Rectangle::new(
+            Point::zero(),
+            Size::new(
+                count as u32 * (FORECAST_SIZE.width - 1) + 1,
+                FORECAST_SIZE.height,
+            ),
+        );
+
+        Self { forecasts, bounds }
     }
 }
 
 impl<'a> View for ForecastRow<'a> {
     fn translate_impl(&mut self, by: Point) {
-        self.layout.translate_mut(by);
+        for forecast in self.forecasts.iter_mut() {
+            forecast.translate_mut(by);
+        }
+        self.bounds.translate_mut(by);
     }
 
     fn bounds(&self) -> Rectangle {
@@ -87,7 +95,7 @@ impl<'a> View for ForecastRow<'a> {
 
 impl<'a> Dimensions for ForecastRow<'a> {
     fn bounding_box(&self) -> Rectangle {
-        self.layout.bounds()
+        self.bounds
     }
 }
 
@@ -96,6 +104,10 @@ impl<'a> Drawable for ForecastRow<'a> {
     type Output = ();
 
     fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
-        self.layout.draw(target)
+        for forecast in self.forecasts.iter() {
+            forecast.draw(target)?;
+        }
+
+        Ok(())
     }
 }
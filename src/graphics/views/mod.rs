@@ -1,4 +1,6 @@
+pub mod battery_icon;
 pub mod feed_group;
 pub mod forecast;
 pub mod forecast_row;
+pub mod qr_code;
 pub mod status_bar;
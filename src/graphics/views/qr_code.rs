@@ -0,0 +1,78 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    pixelcolor::BinaryColor,
+    prelude::{Dimensions, Point, Size},
+    primitives::Rectangle,
+    Drawable,
+};
+use embedded_layout::prelude::*;
+use qrcodegen::QrCodeEcc;
+
+/// White modules around the code. The standard asks for four but two are
+/// enough for phone cameras and leave more space for larger modules.
+const QUIET_ZONE: i32 = 2;
+
+pub struct QrCode {
+    code: qrcodegen::QrCode,
+    scale: u32,
+    bounds: Rectangle,
+}
+
+impl QrCode {
+    /// Encodes the text with the largest module size that fits into a square
+    /// of `max_size` pixels. Returns `None` if the code would not fit.
+    pub fn new(text: &str, max_size: u32) -> Option<Self> {
+        let code = qrcodegen::QrCode::encode_text(text, QrCodeEcc::Low).ok()?;
+
+        let modules = (code.size() + 2 * QUIET_ZONE) as u32;
+        let scale = max_size / modules;
+        if scale == 0 {
+            return None;
+        }
+
+        Some(Self {
+            code,
+            scale,
+            bounds: Rectangle::new(Point::zero(), Size::new_equal(modules * scale)),
+        })
+    }
+}
+
+impl View for QrCode {
+    fn translate_impl(&mut self, by: Point) {
+        self.bounds.translate_mut(by);
+    }
+
+    fn bounds(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl Dimensions for QrCode {
+    fn bounding_box(&self) -> Rectangle {
+        self.bounds
+    }
+}
+
+impl Drawable for QrCode {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D: DrawTarget<Color = BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        target.fill_solid(&self.bounds, BinaryColor::Off)?;
+
+        let module_size = Size::new_equal(self.scale);
+        let origin = self.bounds.top_left + Point::new_equal(QUIET_ZONE * self.scale as i32);
+
+        for y in 0..self.code.size() {
+            for x in 0..self.code.size() {
+                if self.code.get_module(x, y) {
+                    let top_left = origin + Point::new(x, y) * self.scale as i32;
+                    target.fill_solid(&Rectangle::new(top_left, module_size), BinaryColor::On)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! Logs the battery voltage of every wake cycle to estimate how long a charge lasts.
+
+use crate::{
+    battery::{BatteryStatus, CHARGE_THRESHOLD},
+    storage::BASE_DIR,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+};
+
+/// About ten days with the default refresh interval.
+const CAPACITY: usize = 512;
+/// Next slot and number of used slots, both as u16.
+const HEADER_SIZE: u64 = 4;
+/// Unix time as u32 and the voltage as u16.
+const SAMPLE_SIZE: u64 = 6;
+/// Shorter discharge periods are too noisy for an estimate.
+const MIN_ESTIMATE_PERIOD: u32 = 12 * 60 * 60;
+const SECONDS_PER_DAY: f32 = 24.0 * 60.0 * 60.0;
+
+/// The FAT partition only supports 8.3 file names.
+fn path() -> String {
+    format!("{}/history/battery.dat", BASE_DIR)
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Sample {
+    /// Unix time of the measurement.
+    pub time: u32,
+    /// Battery voltage in mV.
+    pub voltage: u16,
+}
+
+/// Ring buffer of the samples with a fixed size on the flash, so a new
+/// sample only overwrites a single slot.
+#[derive(Default)]
+pub struct BatteryHistory {
+    /// Ordered from the oldest to the newest sample.
+    samples: Vec<Sample>,
+    next_slot: u16,
+}
+
+impl BatteryHistory {
+    pub fn load() -> Result<Self> {
+        let file = File::open(path()).context("No battery history yet.")?;
+        let mut reader = BufReader::new(file);
+
+        let next_slot = read_u16(&mut reader)?;
+        let count = read_u16(&mut reader)?;
+        if next_slot as usize >= CAPACITY || count as usize > CAPACITY {
+            anyhow::bail!("Battery history is corrupted.");
+        }
+
+        let mut slots = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            slots.push(Sample {
+                time: read_u32(&mut reader)?,
+                voltage: read_u16(&mut reader)?,
+            });
+        }
+
+        // Once the buffer is full, the oldest sample is in the next slot.
+        if slots.len() == CAPACITY {
+            slots.rotate_left(next_slot as usize);
+        }
+
+        Ok(Self {
+            samples: slots,
+            next_slot,
+        })
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Appends the sample and writes it to the flash.
+    pub fn push(&mut self, sample: Sample) -> Result<()> {
+        fs::create_dir_all(format!("{}/history", BASE_DIR))
+            .context("Could not create the history directory.")?;
+
+        if self.samples.len() == CAPACITY {
+            self.samples.remove(0);
+        }
+        self.samples.push(sample);
+
+        let slot = self.next_slot;
+        self.next_slot = ((slot as usize + 1) % CAPACITY) as u16;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path())
+            .context("Could not open the battery history.")?;
+
+        let mut header = [0; HEADER_SIZE as usize];
+        header[..2].copy_from_slice(&self.next_slot.to_le_bytes());
+        header[2..].copy_from_slice(&(self.samples.len() as u16).to_le_bytes());
+
+        let mut record = [0; SAMPLE_SIZE as usize];
+        record[..4].copy_from_slice(&sample.time.to_le_bytes());
+        record[4..].copy_from_slice(&sample.voltage.to_le_bytes());
+
+        file.seek(SeekFrom::Start(HEADER_SIZE + slot as u64 * SAMPLE_SIZE))
+            .and_then(|_| file.write_all(&record))
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(&header))
+            .context("Could not write the battery history.")?;
+
+        Ok(())
+    }
+
+    /// Samples since the battery was charged the last time.
+    fn discharge_period(&self) -> &[Sample] {
+        let mut start = self.samples.len();
+
+        while start > 0 {
+            let sample = self.samples[start - 1];
+            if BatteryStatus::from_voltage(sample.voltage).external_power {
+                break;
+            }
+            if let Some(next) = self.samples.get(start) {
+                if next.voltage > sample.voltage + CHARGE_THRESHOLD {
+                    break;
+                }
+            }
+            start -= 1;
+        }
+
+        &self.samples[start..]
+    }
+
+    /// Extrapolates the discharge since the last charge. Returns `None` if
+    /// there is not enough data yet.
+    pub fn days_remaining(&self) -> Option<f32> {
+        let period = self.discharge_period();
+        let (first, last) = (period.first()?, period.last()?);
+
+        let duration = last.time.checked_sub(first.time)?;
+        if duration < MIN_ESTIMATE_PERIOD {
+            return None;
+        }
+
+        let first_percentage = BatteryStatus::from_voltage(first.voltage).percentage;
+        let last_percentage = BatteryStatus::from_voltage(last.voltage).percentage;
+        if last_percentage >= first_percentage {
+            return None;
+        }
+
+        let per_second = (first_percentage - last_percentage) as f32 / duration as f32;
+
+        Some(last_percentage as f32 / per_second / SECONDS_PER_DAY)
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16> {
+    let mut bytes = [0; 2];
+    reader
+        .read_exact(&mut bytes)
+        .context("Battery history is incomplete.")?;
+
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader
+        .read_exact(&mut bytes)
+        .context("Battery history is incomplete.")?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
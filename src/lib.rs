@@ -1,10 +1,18 @@
+pub mod auth;
+pub mod battery;
+pub mod board;
+pub mod cache;
 pub mod command;
 pub mod datetime;
 mod definitions;
+pub mod dns;
 pub mod feed;
 pub mod graphics;
+pub mod history;
 pub mod interrupt;
+pub mod lifecycle;
 pub mod nvs;
+pub mod schedule;
 pub mod server;
 pub mod state;
 pub mod stock;
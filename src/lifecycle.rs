@@ -0,0 +1,383 @@
+//! The cycle of a normal run: wake up, connect to the wifi, fetch new data,
+//! show it and go back to deep sleep.
+
+use crate::{
+    battery::PowerLevel,
+    command::{Refresh, RefreshRequest},
+    datetime,
+    graphics::{pages::LowBatteryPage, DisplayRequest},
+    history::Sample,
+    nvs::NvsController,
+    state::State,
+    wifi::{self, SharedWifi, WifiConfig},
+};
+use anyhow::Result;
+use esp_idf_svc::{netif::EspNetifStack, nvs::EspDefaultNvs, sysloop::EspSysLoopStack};
+use log::*;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Longest time to wait for the data sources before drawing anyways.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+/// Longest time to wait for the display to finish an update.
+const RENDER_TIMEOUT: Duration = Duration::from_secs(30);
+/// The refresh interval is stretched by this factor while the battery is low.
+const LOW_BATTERY_INTERVAL_FACTOR: u32 = 3;
+
+/// Unix time of the next regular refresh. It is kept in the RTC memory so
+/// that waking up by the button does not postpone it.
+#[link_section = ".rtc.data"]
+static mut NEXT_REFRESH: u64 = 0;
+/// Failed wifi connections in a row. Kept in the RTC memory to back off
+/// while the network is unreachable.
+#[link_section = ".rtc.data"]
+static mut FAILED_CONNECTIONS: u32 = 0;
+
+/// Retries after a failed connection that use the shortest delay.
+const QUICK_RETRIES: u32 = 3;
+/// Delay before retrying a failed connection, doubled for every further
+/// failure up to the maximum.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Why the device is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    /// Power on or reset.
+    PowerOn,
+    /// The deep sleep timer ran out.
+    Timer,
+    /// The button was pressed during deep sleep.
+    Button,
+}
+
+impl WakeCause {
+    pub fn get() -> Self {
+        match unsafe { esp_idf_sys::esp_sleep_get_wakeup_cause() } {
+            esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeCause::Timer,
+            esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0
+            | esp_idf_sys::esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => WakeCause::Button,
+            _ => WakeCause::PowerOn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Wake,
+    /// Connect to the wifi, then fetch the given source.
+    Connect(Refresh),
+    Fetch(Refresh),
+    /// Draw the page. Stay awake afterwards if the wifi is connected.
+    Render {
+        online: bool,
+    },
+    /// Keep the web interface reachable for a while.
+    Awake,
+    Sleep,
+    /// Show the charge warning and sleep until the button is pressed.
+    Shutdown,
+}
+
+pub struct Lifecycle {
+    pub state: Arc<Mutex<State>>,
+    pub nvs: Arc<Mutex<NvsController>>,
+    pub wifi: SharedWifi,
+    pub netif_stack: Arc<EspNetifStack>,
+    pub sys_loop_stack: Arc<EspSysLoopStack>,
+    pub default_nvs: Arc<EspDefaultNvs>,
+    pub wifi_config: Option<WifiConfig>,
+    pub refresh_tx: Sender<RefreshRequest>,
+    pub display_tx: Sender<DisplayRequest>,
+    pub reconnect_rx: Receiver<WifiConfig>,
+    /// Wakes the device from deep sleep when pulled low.
+    pub button_pin: i32,
+    /// Tells the main thread whether it can continue normally or has to fall
+    /// back to the setup mode because the wifi connection failed.
+    pub startup_tx: Sender<Result<(), String>>,
+}
+
+impl Lifecycle {
+    /// Runs through all phases until the device enters deep sleep.
+    pub fn run(self) -> Result<()> {
+        let mut phase = Phase::Wake;
+        let mut refreshed = false;
+
+        loop {
+            info!("Lifecycle phase: {:?}", phase);
+
+            phase = match phase {
+                Phase::Wake => self.wake(),
+                Phase::Connect(source) => match self.connect() {
+                    Ok(()) => Phase::Fetch(source),
+                    Err(e) => {
+                        // Let the main thread fall back to the setup mode.
+                        self.startup_tx.send(Err(format!("{:#}", e))).ok();
+                        return Err(e);
+                    }
+                },
+                Phase::Fetch(source) => {
+                    self.fetch(source);
+                    refreshed = matches!(source, Refresh::All | Refresh::Essential);
+                    Phase::Render { online: true }
+                }
+                Phase::Render { online } => {
+                    self.render();
+
+                    if online {
+                        Phase::Awake
+                    } else {
+                        Phase::Sleep
+                    }
+                }
+                Phase::Awake => {
+                    self.stay_awake();
+                    Phase::Sleep
+                }
+                Phase::Sleep => {
+                    self.sleep(refreshed);
+                    return Ok(());
+                }
+                Phase::Shutdown => {
+                    self.shutdown();
+                    return Ok(());
+                }
+            };
+        }
+    }
+
+    fn wake(&self) -> Phase {
+        let cause = WakeCause::get();
+        let level = self.state.lock().unwrap().battery.level();
+        info!("Wake up cause: {:?}, power level: {:?}", cause, level);
+
+        match level {
+            PowerLevel::Critical => return Phase::Shutdown,
+            PowerLevel::Low if cause != WakeCause::Button => {
+                return Phase::Connect(Refresh::Essential)
+            }
+            _ if cause != WakeCause::Button => return Phase::Connect(Refresh::All),
+            _ => {}
+        }
+
+        // Switch to the next page and show the data of the last refresh.
+        // Only connect if there is nothing cached for it.
+        let missing_source = {
+            let mut state = self.state.lock().unwrap();
+            state.next_page();
+
+            if let Err(e) = self.nvs.lock().unwrap().store("last_page", &state.page) {
+                warn!("{:?}", e.context("Could not store the page."));
+            }
+
+            Refresh::for_page(&state.page).filter(|&source| !state.has_data(source))
+        };
+
+        match missing_source {
+            Some(source) => Phase::Connect(source),
+            None => {
+                self.startup_tx.send(Ok(())).ok();
+                Phase::Render { online: false }
+            }
+        }
+    }
+
+    fn connect(&self) -> Result<()> {
+        let wifi = wifi::connect(
+            self.wifi_config.as_ref(),
+            Arc::clone(&self.netif_stack),
+            Arc::clone(&self.sys_loop_stack),
+            Arc::clone(&self.default_nvs),
+        )?;
+
+        *self.wifi.lock().unwrap() = Some(wifi);
+        unsafe { FAILED_CONNECTIONS = 0 };
+        self.startup_tx.send(Ok(())).ok();
+
+        Ok(())
+    }
+
+    fn fetch(&self, source: Refresh) {
+        let (done_tx, done_rx) = mpsc::channel();
+        let request = RefreshRequest {
+            source,
+            done: Some(done_tx),
+        };
+
+        if self.refresh_tx.send(request).is_err() || done_rx.recv_timeout(FETCH_TIMEOUT).is_err() {
+            warn!("Data sources did not respond in time.");
+        }
+    }
+
+    fn render(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+
+        if self
+            .display_tx
+            .send(DisplayRequest::UpdateConfirmed(done_tx))
+            .is_err()
+            || done_rx.recv_timeout(RENDER_TIMEOUT).is_err()
+        {
+            warn!("Display did not finish in time.");
+        }
+    }
+
+    /// Stays awake and reconnects whenever a new wifi config is saved.
+    fn stay_awake(&self) {
+        let awake_time = self.state.lock().unwrap().schedule.awake_time();
+        let awake_until = Instant::now() + awake_time;
+
+        while let Some(timeout) = awake_until.checked_duration_since(Instant::now()) {
+            let config = match self.reconnect_rx.recv_timeout(timeout) {
+                Ok(config) => config,
+                Err(_) => break,
+            };
+
+            info!("Reconnect with the new wifi config.");
+            drop(self.wifi.lock().unwrap().take());
+
+            match wifi::connect(
+                Some(&config),
+                Arc::clone(&self.netif_stack),
+                Arc::clone(&self.sys_loop_stack),
+                Arc::clone(&self.default_nvs),
+            ) {
+                Ok(new_wifi) => {
+                    *self.wifi.lock().unwrap() = Some(new_wifi);
+                    self.refresh_tx.send(Refresh::All.into()).ok();
+                }
+                Err(e) => warn!("{:?}", e.context("Could not connect with new wifi config.")),
+            }
+        }
+    }
+
+    /// Sleeps until the next regular refresh or the button is pressed.
+    fn sleep(&self, refreshed: bool) {
+        // Gracefully shut down wifi.
+        info!("Shutdown wifi.");
+        drop(self.wifi.lock().unwrap().take());
+
+        self.flush_display();
+        self.record_battery();
+
+        let now = unix_time();
+        let next_refresh = unsafe { NEXT_REFRESH };
+
+        let sleep_duration = if !refreshed && next_refresh > now {
+            Duration::from_secs(next_refresh - now)
+        } else {
+            let duration = {
+                let state = self.state.lock().unwrap();
+                let mut schedule = state.schedule.clone();
+
+                if state.battery.level() == PowerLevel::Low {
+                    schedule.refresh_interval *= LOW_BATTERY_INTERVAL_FACTOR;
+                }
+
+                schedule.sleep_duration(datetime::get_local_time())
+            };
+
+            unsafe { NEXT_REFRESH = now + duration.as_secs() };
+            duration
+        };
+
+        unsafe {
+            esp_idf_sys::esp_sleep_enable_ext0_wakeup(self.button_pin, 0);
+        }
+
+        info!("Enter deep sleep for {}s now.", sleep_duration.as_secs());
+        enter_deep_sleep(sleep_duration);
+    }
+
+    /// Asks the user to charge the battery and sleeps without a timer. Only
+    /// the button or a reset wakes the device again.
+    fn shutdown(&self) {
+        self.state.lock().unwrap().page = LowBatteryPage.into();
+        self.startup_tx.send(Ok(())).ok();
+
+        self.render();
+        self.record_battery();
+
+        unsafe {
+            esp_idf_sys::esp_sleep_enable_ext0_wakeup(self.button_pin, 0);
+        }
+
+        warn!("Battery is empty, enter deep sleep until the button is pressed.");
+        unsafe { esp_idf_sys::esp_deep_sleep_start() }
+    }
+
+    /// Adds the last measurement to the battery history once per wake cycle.
+    fn record_battery(&self) {
+        let mut state = self.state.lock().unwrap();
+        let (voltage, time) = (state.battery.voltage, unix_time());
+
+        // Skip if the battery or the time are unknown.
+        if voltage == 0 || datetime::get_local_time().is_none() {
+            return;
+        }
+
+        let sample = Sample {
+            time: time as u32,
+            voltage,
+        };
+        if let Err(e) = state.battery_history.push(sample) {
+            warn!("{:?}", e);
+        }
+    }
+
+    /// Updates requested while awake must not be cut off.
+    fn flush_display(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+
+        if self
+            .display_tx
+            .send(DisplayRequest::Flush(done_tx))
+            .is_err()
+            || done_rx.recv_timeout(RENDER_TIMEOUT).is_err()
+        {
+            warn!("Display did not finish in time.");
+        }
+    }
+}
+
+/// Sleeps after the wifi connection failed and the setup mode was shown for a
+/// while. The delay until the next attempt grows while the network stays
+/// unreachable, so the battery is not drained by the access point. The
+/// button wakes the device up earlier.
+pub fn sleep_after_failed_connection(button_pin: i32) {
+    let failed = unsafe {
+        FAILED_CONNECTIONS = FAILED_CONNECTIONS.saturating_add(1);
+        FAILED_CONNECTIONS
+    };
+
+    let doublings = failed.saturating_sub(QUICK_RETRIES).min(16);
+    let delay = (RETRY_DELAY * 2u32.pow(doublings)).min(MAX_RETRY_DELAY);
+
+    unsafe {
+        esp_idf_sys::esp_sleep_enable_ext0_wakeup(button_pin, 0);
+    }
+
+    info!(
+        "Wifi connection failed {} times, retry in {}s.",
+        failed,
+        delay.as_secs()
+    );
+    enter_deep_sleep(delay);
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Waking up from deep sleep starts the application from the beginning.
+fn enter_deep_sleep(duration: Duration) {
+    unsafe { esp_idf_sys::esp_deep_sleep(duration.as_micros() as u64) }
+}
@@ -1,16 +1,22 @@
 use anyhow::{Context, Result};
-use embedded_hal::{adc::OneShot, digital::v2::InputPin};
+use embedded_hal::digital::v2::InputPin;
 // use embedded_hal_alpha::adc::nb::OneShot;
 use esp_feed::{
-    command::Command,
-    datetime, graphics,
+    battery::Battery,
+    board::BoardPins,
+    command::{Command, Refresh},
+    datetime, dns, graphics,
     graphics::{
         display,
         pages::{ConfigPage, PageType},
+        refresh::DisplayConfig,
+        DisplayRequest,
     },
     interrupt,
+    lifecycle::{Lifecycle, WakeCause},
     nvs::NvsController,
-    server::{self, PersonalData, RssData, StockData, WifiData},
+    schedule::Schedule,
+    server::{self, PersonalData, RssData, ServerContext, StockData, WifiData},
     state,
     storage::StorageHandle,
     wifi,
@@ -21,9 +27,12 @@ use esp_idf_svc::{
 };
 use esp_idf_sys as _; // Always keep it imported
 use log::*;
-use std::sync::{
-    mpsc::{self, RecvTimeoutError},
-    Arc, Mutex,
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 // #[allow(dead_code)]
@@ -39,6 +48,23 @@ fn setup_logging() {
     // std::env::set_var("RUST_BACKTRACE", "1");
 }
 
+/// Opens the access point for the configuration and answers all DNS queries
+/// with its address.
+fn start_setup_mode(
+    netif_stack: Arc<EspNetifStack>,
+    sys_loop_stack: Arc<EspSysLoopStack>,
+    default_nvs: Arc<EspDefaultNvs>,
+    shared_wifi: &wifi::SharedWifi,
+    access_point: &wifi::AccessPointConfig,
+) -> Result<()> {
+    let wifi = wifi::create_accesspoint(netif_stack, sys_loop_stack, default_nvs, access_point)?;
+
+    // Dont ever disconnect from the wifi.
+    *shared_wifi.lock().unwrap() = Some(wifi);
+
+    dns::start_captive_dns()
+}
+
 fn main() -> Result<()> {
     // Temporary. Will disappear once ESP-IDF 4.4 is released, but for now it is necessary to call this function once,
     // or else some patches to the runtime implemented by esp-idf-sys might not link properly.
@@ -53,14 +79,20 @@ fn main() -> Result<()> {
     let _storage_handle = StorageHandle::new();
 
     let peripherals = Peripherals::take().unwrap();
-    let pins = peripherals.pins;
+    let pins = BoardPins::take(peripherals.pins)?;
 
-    let vbat_pin = pins.gpio35.into_analog_atten_11db().unwrap();
+    let vbat_pin = pins.vbat;
     let vbat_adc = PoweredAdc::new(peripherals.adc1, Default::default()).unwrap();
-    let mut vbat = (vbat_pin, vbat_adc);
+    let mut battery = Battery::new(vbat_pin, vbat_adc);
 
-    let button_pin = pins.gpio39.into_input().unwrap();
-    let setup_mode = button_pin.is_low().unwrap();
+    let button_pin = pins.button;
+    let wake_cause = WakeCause::get();
+
+    // The button may still be held after it woke the device up.
+    let setup_mode = button_pin.is_low().unwrap() && wake_cause != WakeCause::Button;
+    while button_pin.is_low().unwrap() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
 
     let netif_stack = Arc::new(EspNetifStack::new()?);
     let sys_loop_stack = Arc::new(EspSysLoopStack::new()?);
@@ -68,50 +100,72 @@ fn main() -> Result<()> {
 
     let button1_state = interrupt::register_button_interrupt(button_pin.pin());
 
-    let mut nvs_controller = NvsController::new(Arc::clone(&default_nvs))?;
-    let wifi_config = nvs_controller.get_config::<WifiData>().ok().map(Into::into);
-    let personal_config = nvs_controller.get_config::<PersonalData>().ok();
-    let rss_config = nvs_controller.get_config::<RssData>().ok();
-    let stock_config = nvs_controller
-        .get_config::<StockData>()
-        .unwrap_or(StockData {
-            symbol: "IBM".into(),
-        });
+    let nvs_controller = Arc::new(Mutex::new(NvsController::new(Arc::clone(&default_nvs))?));
+    let mut nvs = nvs_controller.lock().unwrap();
+
+    // Generated once on the first boot so every device has its own access point.
+    let access_point = match nvs.get_config::<wifi::AccessPointConfig>() {
+        Ok(config) => config,
+        Err(_) => {
+            let config = wifi::AccessPointConfig::generate();
+            nvs.store_config(&config)?;
+            config
+        }
+    };
+    let wifi_config = nvs.get_config::<WifiData>().ok().map(Into::into);
+    let personal_config = nvs.get_config::<PersonalData>().ok();
+    let rss_config = nvs.get_config::<RssData>().ok();
+    let stock_config = nvs.get_config::<StockData>().unwrap_or(StockData {
+        symbol: "IBM".into(),
+    });
+    let schedule = nvs.get_config::<Schedule>().unwrap_or_default();
+    let display_config = nvs.get_config::<DisplayConfig>().unwrap_or_default();
 
     let location = personal_config
         .map(|data| data.location)
         .unwrap_or_default();
 
     let start_page = {
-        let page = nvs_controller.get("last_page").unwrap_or_default();
+        let page = nvs.get("last_page").unwrap_or_default();
 
-        if page == PageType::ConfigPage(ConfigPage) {
+        if matches!(page, PageType::ConfigPage(_) | PageType::LowBatteryPage(_)) {
             Default::default()
         } else {
             page
         }
     };
+    drop(nvs);
 
     let (command_tx, command_rx) = mpsc::channel();
     let (update_page_tx, update_page_rx) = mpsc::channel();
+    let (refresh_tx, refresh_rx) = mpsc::channel();
+    let (reconnect_tx, reconnect_rx) = mpsc::channel::<wifi::WifiConfig>();
 
     let state = Arc::new(Mutex::new(state::State::new(
         setup_mode,
+        access_point.clone(),
         wifi_config.clone(),
         location,
         start_page,
         &stock_config.symbol,
+        schedule,
+        display_config,
     )));
 
+    {
+        let mut state = state.lock().unwrap();
+        state.load_cache();
+
+        // Get battery voltage for pages that are drawn without a refresh.
+        if let Some(status) = battery.measure() {
+            state.battery = status;
+        }
+    }
+
     let spi3 = peripherals.spi3;
-    let busy = pins.gpio4.into_input()?;
-    let rst = pins.gpio16.into_output()?;
-    let dc = pins.gpio17.into_output()?;
-    let cs = pins.gpio5.into_output()?;
-    let sclk = pins.gpio18;
-    let mosi = pins.gpio23;
-
-    let mut display = display::get_epd_display(busy, rst, dc, cs, sclk, mosi, spi3)?;
+    let mut display = display::get_epd_display(
+        pins.busy, pins.rst, pins.dc, pins.cs, pins.sclk, pins.mosi, spi3,
+    )?;
     {
         let state = Arc::clone(&state);
 
@@ -122,71 +176,96 @@ fn main() -> Result<()> {
             .context("Could not create display thread.")?;
     }
 
+    let shared_wifi: wifi::SharedWifi = Default::default();
+
     if setup_mode {
-        let wifi = wifi::create_accesspoint(
+        start_setup_mode(
             Arc::clone(&netif_stack),
             Arc::clone(&sys_loop_stack),
             Arc::clone(&default_nvs),
+            &shared_wifi,
+            &access_point,
         )?;
-
-        // Dont ever disconnect from the wifi.
-        std::mem::forget(wifi);
     };
 
     if !setup_mode {
-        let (wifi_tx, wifi_rx) = mpsc::channel();
+        let (startup_tx, startup_rx) = mpsc::channel();
+        let lifecycle = Lifecycle {
+            state: Arc::clone(&state),
+            nvs: Arc::clone(&nvs_controller),
+            wifi: Arc::clone(&shared_wifi),
+            netif_stack: Arc::clone(&netif_stack),
+            sys_loop_stack: Arc::clone(&sys_loop_stack),
+            default_nvs: Arc::clone(&default_nvs),
+            wifi_config,
+            refresh_tx: refresh_tx.clone(),
+            display_tx: update_page_tx.clone(),
+            reconnect_rx,
+            button_pin: button_pin.pin(),
+            startup_tx,
+        };
 
         std::thread::Builder::new()
-            .name("Deep Sleep".into())
+            .name("Lifecycle".into())
             .stack_size(10240)
-            .spawn(move || -> Result<()> {
-                loop {
-                    let wifi = wifi::connect(
-                        wifi_config.as_ref(),
-                        Arc::clone(&netif_stack),
-                        Arc::clone(&sys_loop_stack),
-                        Arc::clone(&default_nvs),
-                    )?;
-
-                    // Notify main thread.
-                    wifi_tx.send(()).unwrap();
-
-                    std::thread::sleep(std::time::Duration::from_secs(90));
-
-                    // Gracefully shut down wifi.
-                    info!("Shutdown wifi.");
-                    drop(wifi);
-
-                    info!("Enter deep sleep now.");
-                    unsafe {
-                        esp_idf_sys::esp_deep_sleep(1800 * 1_000_000u64);
-                    }
-
-                    // NOTE: Currently the ESP crashes after deep sleep wakeup.
-                    // BUT this is not a huge problem and can be exploited to
-                    // restart the ESP which it should do anyways.
-
-                    info!("Wakeup from deep sleep.");
-                }
-            })
-            .context("Could not create deep sleep thread.")?;
+            .spawn(move || lifecycle.run())
+            .context("Could not create lifecycle thread.")?;
 
         // Wait until wifi connection is established.
-        wifi_rx.recv().unwrap();
+        if let Err(reason) = startup_rx.recv().unwrap() {
+            warn!(
+                "Wifi connection failed, fall back to setup mode: {}",
+                reason
+            );
+
+            start_setup_mode(
+                Arc::clone(&netif_stack),
+                Arc::clone(&sys_loop_stack),
+                Arc::clone(&default_nvs),
+                &shared_wifi,
+                &access_point,
+            )?;
+
+            {
+                let mut state = state.lock().unwrap();
+                state.setup_mode = true;
+                state.setup_reason = Some(reason);
+                state.page = ConfigPage.into();
+            }
+            update_page_tx.send(DisplayRequest::Update)?;
+
+            // Try again later in case the network was only gone temporarily.
+            let setup_time = state.lock().unwrap().schedule.setup_time();
+            let button_pin = button_pin.pin();
+            std::thread::Builder::new()
+                .name("Fallback".into())
+                .spawn(move || {
+                    std::thread::sleep(setup_time);
+                    lifecycle::sleep_after_failed_connection(button_pin);
+                })
+                .context("Could not create fallback thread.")?;
+        }
     }
 
     {
-        let command_tx = command_tx.clone();
+        let context = ServerContext {
+            command_tx: command_tx.clone(),
+            nvs: Arc::clone(&nvs_controller),
+            state: Arc::clone(&state),
+            wifi: Arc::clone(&shared_wifi),
+        };
+
+        let setup_time = state.lock().unwrap().schedule.setup_time();
 
         std::thread::Builder::new()
             .name("Server".into())
             .spawn(move || -> Result<()> {
-                let _server = server::httpd(command_tx)?;
+                let _server = server::httpd(context)?;
 
-                // Let the server run for 10 minutes, then shut it down.
+                // Let the server run as long as the setup mode, then shut it down.
                 // Note: In normal mode this actually does nothing because
                 // the deep sleep will shut it down anyways.
-                std::thread::sleep(std::time::Duration::from_secs(600));
+                std::thread::sleep(setup_time);
 
                 Ok(())
             })?;
@@ -201,8 +280,7 @@ fn main() -> Result<()> {
         });
 
         if let Ok(url) = url::Url::parse(&rss_data.url) {
-            let urls = [url];
-            controller.urls_mut().extend_from_slice(&urls);
+            controller.set_urls(vec![url]);
         }
     }
 
@@ -211,45 +289,64 @@ fn main() -> Result<()> {
         let update_page_tx = update_page_tx.clone();
 
         move || {
-            fn fetch_data(state: &mut state::State) -> Result<()> {
-                let feed_controller = &mut state.feed_controller;
-                info!("Fetching feeds: {:?}", feed_controller.urls_mut());
-                feed_controller
-                    .refresh()
-                    .context("Could not retrieve feeds.")?;
-
-                let weather_controller = &mut state.weather_controller;
-                info!("Fetching weather.");
-                weather_controller
-                    .refresh(&state.location)
-                    .context("Could not retrieve weather data.")?;
-
-                let stock_controller = &mut state.stock_controller;
-                info!("Fetching stock info.");
-                stock_controller
-                    .refresh()
-                    .context("Could not retrieve stock info.")?;
-                Ok(())
+            fn fetch_data(state: &mut state::State, source: Refresh) {
+                if source.includes(Refresh::Feed) {
+                    let feed_controller = &mut state.feed_controller;
+                    info!("Fetching feeds: {:?}", feed_controller.urls_mut());
+                    let result = feed_controller
+                        .refresh()
+                        .context("Could not retrieve feeds.");
+                    state.refresh_status.feed.update(result);
+                }
+
+                if source.includes(Refresh::Weather) {
+                    let weather_controller = &mut state.weather_controller;
+                    info!("Fetching weather.");
+                    let result = weather_controller
+                        .refresh(&state.location)
+                        .context("Could not retrieve weather data.");
+                    state.refresh_status.weather.update(result);
+                }
+
+                if source.includes(Refresh::Stock) {
+                    let stock_controller = &mut state.stock_controller;
+                    info!("Fetching stock info.");
+                    let result = stock_controller
+                        .refresh()
+                        .context("Could not retrieve stock info.");
+                    state.refresh_status.stock.update(result);
+                }
             }
 
             loop {
+                // Wait for the next regular refresh unless new data is needed
+                // right away, e.g. after waking up or a changed config.
+                let refresh_interval = state.lock().unwrap().schedule.refresh_interval();
+                let request = refresh_rx
+                    .recv_timeout(refresh_interval)
+                    .unwrap_or_else(|_| Refresh::All.into());
+
                 {
                     let state = &mut state.lock().unwrap();
 
-                    if let Err(e) = fetch_data(state) {
-                        log::warn!("{:?}", e.context("Could not retrieve new data."));
-                    }
+                    fetch_data(state, request.source);
+                    state.store_cache(request.source);
 
-                    // Get battery voltage
-                    if let Ok(val) = vbat.1.read(&mut vbat.0) {
-                        state.battery = val * 2; // 1/2 Voltage divider
+                    if let Some(status) = battery.measure() {
+                        state.battery = status;
                     }
                 }
 
-                // Update page to show new data.
-                update_page_tx.send(()).ok();
-
-                std::thread::sleep(std::time::Duration::from_secs(1200));
+                match request.done {
+                    // The requester takes care of updating the display.
+                    Some(done) => {
+                        done.send(()).ok();
+                    }
+                    // Update page to show new data.
+                    None => {
+                        update_page_tx.send(DisplayRequest::Update).ok();
+                    }
+                }
             }
         }
     };
@@ -260,40 +357,114 @@ fn main() -> Result<()> {
         .spawn(fetching_thread)
         .context("Could not create feed fetching thread.")?;
 
+    // In normal mode the lifecycle requests the first refresh.
+    if state.lock().unwrap().setup_mode {
+        refresh_tx.send(Refresh::All.into())?;
+    }
+
     loop {
-        match command_rx.recv_timeout(std::time::Duration::from_millis(750)) {
+        match command_rx.recv_timeout(Duration::from_millis(750)) {
             Ok(Command::SwitchPage) => {
                 let mut state = state.lock().unwrap();
                 state.next_page();
 
-                nvs_controller.store("last_page", &state.page)?;
+                nvs_controller
+                    .lock()
+                    .unwrap()
+                    .store("last_page", &state.page)?;
 
-                update_page_tx.send(())?;
+                update_page_tx.send(DisplayRequest::Update)?;
+            }
+            Ok(Command::SetPage(page)) => {
+                let mut state = state.lock().unwrap();
+                state.page = page;
+                info!("Switched page to {:?}", state.page);
+
+                nvs_controller
+                    .lock()
+                    .unwrap()
+                    .store("last_page", &state.page)?;
+
+                update_page_tx.send(DisplayRequest::Update)?;
             }
             Ok(Command::SavePersonalConfig(ref config)) => {
                 info!("Save this personal config: {:?}", config);
 
-                nvs_controller.store_config(config)?;
+                nvs_controller.lock().unwrap().store_config(config)?;
 
                 let state = &mut state.lock().unwrap();
                 state.location = config.location.clone();
+
+                refresh_tx.send(Refresh::Weather.into())?;
             }
             Ok(Command::SaveWifiConfig(ref config)) => {
                 info!("Save this wifi config: {:?}", config);
 
-                nvs_controller.store_config(config)?;
+                nvs_controller.lock().unwrap().store_config(config)?;
+
+                let state = &mut state.lock().unwrap();
+                let wifi_config: wifi::WifiConfig = config.clone().into();
+                state.wifi = Some(wifi_config.clone());
 
-                // let state = &mut state.lock().unwrap(); // TODO
+                // In setup mode the access point has to stay up, the new
+                // config is used after leaving the setup mode.
+                if !state.setup_mode {
+                    reconnect_tx.send(wifi_config)?;
+                }
             }
             Ok(Command::SaveRssConfig(ref config)) => {
                 info!("Save this rss config: {:?}", config);
 
-                nvs_controller.store_config(config)?;
+                nvs_controller.lock().unwrap().store_config(config)?;
+
+                let state = &mut state.lock().unwrap();
+                match url::Url::parse(&config.url) {
+                    Ok(url) => state.feed_controller.set_urls(vec![url]),
+                    Err(e) => warn!("Invalid feed url {}: {}", config.url, e),
+                }
+
+                refresh_tx.send(Refresh::Feed.into())?;
             }
             Ok(Command::SaveStockConfig(ref config)) => {
                 info!("Save this stock config: {:?}", config);
 
-                nvs_controller.store_config(config)?;
+                nvs_controller.lock().unwrap().store_config(config)?;
+
+                let state = &mut state.lock().unwrap();
+                state.stock_controller.set_symbol(&config.symbol);
+
+                refresh_tx.send(Refresh::Stock.into())?;
+            }
+            Ok(Command::SaveScheduleConfig(config)) => {
+                info!("Save this schedule config: {:?}", config);
+
+                nvs_controller.lock().unwrap().store_config(&config)?;
+
+                // Takes effect with the next refresh or deep sleep.
+                state.lock().unwrap().schedule = config;
+            }
+            Ok(Command::SaveDisplayConfig(config)) => {
+                info!("Save this display config: {:?}", config);
+
+                nvs_controller.lock().unwrap().store_config(&config)?;
+
+                state.lock().unwrap().display_config = config;
+
+                // Show the new orientation right away.
+                update_page_tx.send(DisplayRequest::Update)?;
+            }
+            Ok(Command::SaveAdminCredentials(ref credentials)) => {
+                info!("Save new admin password.");
+
+                nvs_controller.lock().unwrap().store_config(credentials)?;
+            }
+            Ok(Command::SaveAccessPointConfig(config)) => {
+                info!("Save new access point credentials: {}", config.ssid);
+
+                nvs_controller.lock().unwrap().store_config(&config)?;
+
+                // Used the next time the setup mode is started.
+                state.lock().unwrap().access_point = config;
             }
             Err(RecvTimeoutError::Timeout) => {
                 // Check if a button was pressed in the meanwhile.
@@ -309,6 +480,18 @@ fn main() -> Result<()> {
                 for _ in 0..btn1_pressed {
                     command_tx.send(Command::SwitchPage)?;
                 }
+
+                // Show how to open the settings once a device joined the access point.
+                let mut state = state.lock().unwrap();
+                if state.setup_mode {
+                    let clients = wifi::accesspoint_station_count();
+                    let changed = (clients > 0) != (state.access_point_clients > 0);
+                    state.access_point_clients = clients;
+
+                    if changed {
+                        update_page_tx.send(DisplayRequest::Update)?;
+                    }
+                }
             }
             _ => {}
         }
@@ -0,0 +1,92 @@
+//! When the device wakes up to refresh its data and how long it stays awake.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::Time;
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Hours of the day without any refresh, e.g. at night.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct QuietHours {
+    /// First hour of the quiet time (0-23).
+    pub start: u8,
+    /// First hour after the quiet time (0-23).
+    pub end: u8,
+}
+
+impl QuietHours {
+    /// Whether the given second of the day lies within the quiet time. The
+    /// quiet time may wrap around midnight.
+    fn contains(&self, second_of_day: u32) -> bool {
+        let hour = (second_of_day / 3600) as u8;
+
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schedule {
+    /// Minutes between two refreshes.
+    pub refresh_interval: u32,
+    /// Seconds the device stays awake after a refresh before it goes back
+    /// to deep sleep. The web interface can only be reached during this time.
+    pub awake_time: u32,
+    /// Minutes the setup mode stays active before the device restarts.
+    pub setup_time: u32,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            refresh_interval: 30,
+            awake_time: 90,
+            setup_time: 10,
+            quiet_hours: None,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.refresh_interval as u64 * 60)
+    }
+
+    pub fn awake_time(&self) -> Duration {
+        Duration::from_secs(self.awake_time as u64)
+    }
+
+    pub fn setup_time(&self) -> Duration {
+        Duration::from_secs(self.setup_time as u64 * 60)
+    }
+
+    /// How long to sleep until the next refresh. If it would fall into the
+    /// quiet hours, sleep until they are over instead. Without a known local
+    /// time only the refresh interval is used.
+    pub fn sleep_duration(&self, now: Option<Time>) -> Duration {
+        let interval = self.refresh_interval();
+
+        let (quiet_hours, now) = match (self.quiet_hours, now) {
+            (Some(quiet_hours), Some(now)) => (quiet_hours, now),
+            _ => return interval,
+        };
+
+        let now = now.hour() as u32 * 3600 + now.minute() as u32 * 60 + now.second() as u32;
+        let wakeup = (now + interval.as_secs() as u32) % SECONDS_PER_DAY;
+
+        let end = quiet_hours.end as u32 * 3600;
+        let until_end = (end + SECONDS_PER_DAY - now) % SECONDS_PER_DAY;
+
+        if quiet_hours.contains(wakeup) && until_end > 0 {
+            Duration::from_secs(until_end as u64)
+        } else {
+            interval
+        }
+    }
+}
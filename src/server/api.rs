@@ -0,0 +1,353 @@
+//! JSON REST API to read and modify the configuration and the state.
+
+use super::{
+    parse_config, read_body, set_protected_handler, ConfigData, PersonalData, RssData,
+    ServerContext, StockData, ValidationErrors, WifiData,
+};
+use crate::{
+    battery::BatteryStatus,
+    command::Command,
+    graphics::{pages::PageType, refresh::DisplayConfig},
+    history::Sample,
+    nvs::NvsController,
+    schedule::Schedule,
+    state::RefreshStatus,
+    wifi,
+};
+use anyhow::Result;
+use embedded_svc::http::{
+    server::{registry::Registry, Request, ResponseData},
+    Method, SendHeaders,
+};
+use esp_idf_svc::http::server::EspHttpServer;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+}
+
+#[derive(Serialize)]
+struct StateResponse<'a> {
+    page: &'a str,
+    setup_mode: bool,
+    location: &'a str,
+    battery: BatteryStatus,
+    refresh_status: &'a RefreshStatus,
+}
+
+#[derive(Serialize)]
+struct BatteryHistoryResponse<'a> {
+    samples: &'a [Sample],
+    days_remaining: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PageRequest {
+    /// Either the name of a page or "next".
+    page: String,
+}
+
+/// All configurations combined into a single document for backup and restore.
+#[derive(Serialize, Deserialize, Default)]
+struct ConfigBundle {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    personal: Option<PersonalData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wifi: Option<WifiData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rss: Option<RssData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stock: Option<StockData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<DisplayConfig>,
+}
+
+impl ConfigBundle {
+    fn load(nvs: &NvsController, redact: bool) -> Self {
+        fn load_config<T>(nvs: &NvsController, redact: bool) -> Option<T>
+        where
+            for<'de> T: ConfigData<'de>,
+        {
+            let mut config = nvs.get_config::<T>().ok()?;
+            if redact {
+                config.redact();
+            }
+            Some(config)
+        }
+
+        Self {
+            personal: load_config(nvs, redact),
+            wifi: load_config(nvs, redact),
+            rss: load_config(nvs, redact),
+            stock: load_config(nvs, redact),
+            schedule: load_config(nvs, redact),
+            display: load_config(nvs, redact),
+        }
+    }
+
+    fn restore_secrets(&mut self, nvs: &NvsController) {
+        fn restore_config<T>(nvs: &NvsController, config: &mut Option<T>)
+        where
+            for<'de> T: ConfigData<'de>,
+        {
+            if let (Some(config), Ok(stored)) = (config, nvs.get_config::<T>()) {
+                config.restore_secrets(&stored);
+            }
+        }
+
+        restore_config(nvs, &mut self.personal);
+        restore_config(nvs, &mut self.wifi);
+        restore_config(nvs, &mut self.rss);
+        restore_config(nvs, &mut self.stock);
+        restore_config(nvs, &mut self.schedule);
+        restore_config(nvs, &mut self.display);
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        fn validate_config<T>(errors: &mut ValidationErrors, config: &Option<T>)
+        where
+            for<'de> T: ConfigData<'de>,
+        {
+            if let Some(Err(config_errors)) = config.as_ref().map(ConfigData::validate) {
+                errors.merge(T::key(), config_errors);
+            }
+        }
+
+        let mut errors = ValidationErrors::new();
+
+        validate_config(&mut errors, &self.personal);
+        validate_config(&mut errors, &self.wifi);
+        validate_config(&mut errors, &self.rss);
+        validate_config(&mut errors, &self.stock);
+        validate_config(&mut errors, &self.schedule);
+        validate_config(&mut errors, &self.display);
+
+        errors.into_result()
+    }
+
+    fn into_commands(self) -> Vec<Command> {
+        let mut commands = Vec::new();
+
+        commands.extend(self.personal.map(Into::into));
+        commands.extend(self.wifi.map(Into::into));
+        commands.extend(self.rss.map(Into::into));
+        commands.extend(self.stock.map(Into::into));
+        commands.extend(self.schedule.map(Into::into));
+        commands.extend(self.display.map(Into::into));
+
+        commands
+    }
+}
+
+pub(super) fn json_response<T>(status: u16, value: &T) -> Result<ResponseData>
+where
+    T: Serialize,
+{
+    let mut response = ResponseData::new(status).body(serde_json::to_string(value)?.into());
+    response.set_content_type("application/json");
+
+    Ok(response)
+}
+
+pub(super) fn error_response(status: u16, error: &str) -> Result<ResponseData> {
+    json_response(status, &ErrorResponse { error })
+}
+
+fn set_config_handlers<T>(server: &mut EspHttpServer, context: &ServerContext) -> Result<()>
+where
+    for<'de> T: ConfigData<'de>,
+{
+    let uri = format!("/api/config/{}", T::key());
+
+    let nvs = context.nvs.clone();
+    set_protected_handler(server, &uri, Method::Get, &context.nvs, move |_| {
+        let config = nvs.lock().unwrap().get_config::<T>();
+
+        match config {
+            Ok(mut config) => {
+                config.redact();
+                json_response(200, &config)
+            }
+            Err(_) => error_response(404, "Not configured yet."),
+        }
+    })?;
+
+    let nvs = context.nvs.clone();
+    let command_tx = context.command_tx.clone();
+    set_protected_handler(server, &uri, Method::Put, &context.nvs, move |req| {
+        let body = read_body(req)?;
+
+        let config: T = match parse_config(&body, &nvs) {
+            Ok(config) => config,
+            Err(errors) => return json_response(400, &errors),
+        };
+
+        // Like GET, the response must not contain the restored keys.
+        let mut redacted = config.clone();
+        redacted.redact();
+        let response = json_response(200, &redacted);
+        command_tx.send(config.into())?;
+
+        response
+    })?;
+
+    Ok(())
+}
+
+pub fn set_handlers(server: &mut EspHttpServer, context: &ServerContext) -> Result<()> {
+    set_config_handlers::<PersonalData>(server, context)?;
+    set_config_handlers::<WifiData>(server, context)?;
+    set_config_handlers::<RssData>(server, context)?;
+    set_config_handlers::<StockData>(server, context)?;
+    set_config_handlers::<Schedule>(server, context)?;
+    set_config_handlers::<DisplayConfig>(server, context)?;
+    set_config_handlers::<wifi::AccessPointConfig>(server, context)?;
+
+    let nvs = context.nvs.clone();
+    set_protected_handler(
+        server,
+        "/api/config/export",
+        Method::Get,
+        &context.nvs,
+        move |req| {
+            let redact = req
+                .query_string()
+                .split('&')
+                .any(|param| param == "redact" || param == "redact=true");
+
+            let bundle = ConfigBundle::load(&nvs.lock().unwrap(), redact);
+
+            let mut response = json_response(200, &bundle)?;
+            response.set_header(
+                "Content-Disposition",
+                "attachment; filename=\"esp-feed-config.json\"",
+            );
+
+            Ok(response)
+        },
+    )?;
+
+    let nvs = context.nvs.clone();
+    let command_tx = context.command_tx.clone();
+    set_protected_handler(
+        server,
+        "/api/config/import",
+        Method::Post,
+        &context.nvs,
+        move |req| {
+            let body = read_body(req)?;
+
+            let mut bundle: ConfigBundle = match serde_json::from_slice(&body) {
+                Ok(bundle) => bundle,
+                Err(e) => {
+                    let errors =
+                        ValidationErrors::single("body", format!("Ungültige Datei: {}", e));
+                    return json_response(400, &errors);
+                }
+            };
+
+            // A redacted export has no wifi keys, keep the stored ones in this case.
+            // Keys of networks that are not stored are reported by the validation.
+            bundle.restore_secrets(&nvs.lock().unwrap());
+
+            if let Err(errors) = bundle.validate() {
+                return json_response(400, &errors);
+            }
+
+            for command in bundle.into_commands() {
+                command_tx.send(command)?;
+            }
+
+            Ok(ResponseData::new(200).body("Wiederhergestellt!".into()))
+        },
+    )?;
+
+    let shared_wifi = context.wifi.clone();
+    set_protected_handler(
+        server,
+        "/api/wifi/scan",
+        Method::Get,
+        &context.nvs,
+        move |_| {
+            let mut shared_wifi = shared_wifi.lock().unwrap();
+
+            match shared_wifi.as_mut() {
+                Some(wifi) => json_response(200, &wifi::scan(wifi)?),
+                None => error_response(503, "Wifi is not running."),
+            }
+        },
+    )?;
+
+    let state = context.state.clone();
+    set_protected_handler(server, "/api/state", Method::Get, &context.nvs, move |_| {
+        let state = state.lock().unwrap();
+
+        json_response(
+            200,
+            &StateResponse {
+                page: state.page.name(),
+                setup_mode: state.setup_mode,
+                location: &state.location,
+                battery: state.battery,
+                refresh_status: &state.refresh_status,
+            },
+        )
+    })?;
+
+    let state = context.state.clone();
+    set_protected_handler(
+        server,
+        "/api/battery",
+        Method::Get,
+        &context.nvs,
+        move |_| {
+            let state = state.lock().unwrap();
+
+            json_response(
+                200,
+                &BatteryHistoryResponse {
+                    samples: state.battery_history.samples(),
+                    days_remaining: state.battery_history.days_remaining(),
+                },
+            )
+        },
+    )?;
+
+    let command_tx = context.command_tx.clone();
+    set_protected_handler(
+        server,
+        "/api/page",
+        Method::Post,
+        &context.nvs,
+        move |req| {
+            let body = read_body(req)?;
+
+            let request: PageRequest = match serde_json::from_slice(&body) {
+                Ok(request) => request,
+                Err(e) => return error_response(400, &e.to_string()),
+            };
+
+            let command = if request.page == "next" {
+                Command::SwitchPage
+            } else {
+                // The setup and the charge warning pages are only shown by the
+                // device itself.
+                match PageType::from_name(&request.page) {
+                    Some(PageType::ConfigPage(_) | PageType::LowBatteryPage(_)) | None => {
+                        return error_response(400, "Unknown page.")
+                    }
+                    Some(page) => Command::SetPage(page),
+                }
+            };
+
+            command_tx.send(command)?;
+
+            json_response(200, &request)
+        },
+    )?;
+
+    Ok(())
+}
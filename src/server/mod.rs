@@ -0,0 +1,695 @@
+//! Webserver that provides a web interface to configure the application.
+
+mod api;
+mod validation;
+
+pub use validation::ValidationErrors;
+
+use crate::{
+    auth::{self, AdminCredentials, AdminPassword},
+    command::Command,
+    definitions::AP_IP,
+    graphics::refresh::DisplayConfig,
+    nvs::NvsController,
+    schedule::Schedule,
+    state::State,
+    storage::BASE_DIR,
+    wifi::{AccessPointConfig, SharedWifi, WifiConfig, WifiNetwork},
+};
+use anyhow::{Context, Result};
+use embedded_svc::{
+    http::{
+        server::{registry::Registry, Request, ResponseData},
+        Headers, Method, SendHeaders,
+    },
+    io::StdIO,
+};
+use esp_idf_svc::http::server::{Configuration, EspHttpRequest, EspHttpServer};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::Read,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+use url::Url;
+
+/// URLs that operating systems request to detect a captive portal.
+const CAPTIVE_PORTAL_PROBES: &[&str] = &[
+    "/generate_204",              // Android
+    "/gen_204",                   // Android
+    "/hotspot-detect.html",       // Apple
+    "/library/test/success.html", // Apple
+    "/connecttest.txt",           // Windows
+    "/ncsi.txt",                  // Windows
+    "/redirect",                  // Windows
+    "/canonical.html",            // Firefox
+    "/success.txt",               // Firefox
+];
+
+/// Everything the request handlers need access to.
+#[derive(Clone)]
+pub struct ServerContext {
+    pub command_tx: Sender<Command>,
+    pub nvs: Arc<Mutex<NvsController>>,
+    pub state: Arc<Mutex<State>>,
+    pub wifi: SharedWifi,
+}
+
+pub trait ConfigData<'de>:
+    Deserialize<'de> + Serialize + Clone + std::fmt::Debug + Into<Command>
+{
+    fn key() -> &'static str;
+
+    /// Checks the submitted values before they are stored.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        Ok(())
+    }
+
+    /// Removes secrets before the config leaves the device.
+    fn redact(&mut self) {}
+
+    /// Fills in the secrets that were left out by `redact` from the stored config.
+    fn restore_secrets(&mut self, _stored: &Self) {}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PersonalData {
+    pub name: String,
+    pub location: String,
+}
+
+impl<'de> ConfigData<'de> for PersonalData {
+    fn key() -> &'static str {
+        "personal"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            !self.name.trim().is_empty(),
+            "name",
+            "Bitte einen Namen angeben.",
+        );
+        errors.check(
+            self.name.chars().count() <= 32,
+            "name",
+            "Der Name darf höchstens 32 Zeichen lang sein.",
+        );
+        errors.check(
+            !self.location.trim().is_empty(),
+            "location",
+            "Bitte einen Ort angeben.",
+        );
+
+        errors.into_result()
+    }
+}
+
+impl From<PersonalData> for Command {
+    fn from(config: PersonalData) -> Self {
+        Command::SavePersonalConfig(config)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "WifiDataRepr")]
+pub struct WifiData {
+    pub networks: Vec<WifiNetwork>,
+    /// Hostname that is sent to the DHCP server. It is not used for networks
+    /// with a static IP, as there is no other way to announce it.
+    pub hostname: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WifiDataRepr {
+    Networks {
+        networks: Vec<WifiNetwork>,
+        #[serde(default)]
+        hostname: Option<String>,
+    },
+    /// Config that was stored before multiple networks were supported.
+    Single(WifiNetwork),
+}
+
+impl From<WifiDataRepr> for WifiData {
+    fn from(repr: WifiDataRepr) -> Self {
+        match repr {
+            WifiDataRepr::Networks { networks, hostname } => Self { networks, hostname },
+            WifiDataRepr::Single(network) => Self {
+                networks: vec![network],
+                hostname: None,
+            },
+        }
+    }
+}
+
+impl<'de> ConfigData<'de> for WifiData {
+    fn key() -> &'static str {
+        "wifi"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        const MAX_NETWORKS: usize = 8;
+
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            !self.networks.is_empty(),
+            "networks",
+            "Bitte mindestens ein WLAN angeben.",
+        );
+        errors.check(
+            self.networks.len() <= MAX_NETWORKS,
+            "networks",
+            "Es können höchstens 8 WLANs gespeichert werden.",
+        );
+
+        for (i, network) in self.networks.iter().enumerate() {
+            let ssid_field = format!("networks.{}.ssid", i);
+
+            errors.check(
+                !network.ssid.is_empty(),
+                ssid_field.as_str(),
+                "Bitte eine SSID angeben.",
+            );
+            errors.check(
+                network.ssid.len() <= 32,
+                ssid_field.as_str(),
+                "Die SSID darf höchstens 32 Bytes lang sein.",
+            );
+            errors.check(
+                !self.networks[..i]
+                    .iter()
+                    .any(|other| other.ssid == network.ssid),
+                ssid_field.as_str(),
+                "Dieses WLAN ist bereits gespeichert.",
+            );
+            // Happens with a redacted export that is imported on another device.
+            if network.pass.is_none() {
+                errors.add(
+                    format!("networks.{}.pass", i),
+                    format!(
+                        "Für das WLAN {} ist kein Schlüssel gespeichert, bitte angeben.",
+                        network.ssid
+                    ),
+                );
+            }
+            let pass_len = network.pass.as_ref().map_or(0, String::len);
+            errors.check(
+                pass_len == 0 || (8..=64).contains(&pass_len),
+                format!("networks.{}.pass", i),
+                "Der Schlüssel muss zwischen 8 und 64 Zeichen lang sein.",
+            );
+
+            if let Some(ref static_ip) = network.ip {
+                match static_ip.prefix_len() {
+                    Some(len) => {
+                        let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+                        let same_subnet =
+                            u32::from(static_ip.ip) & mask == u32::from(static_ip.gateway) & mask;
+
+                        errors.check(
+                            same_subnet,
+                            format!("networks.{}.ip.gateway", i),
+                            "Das Gateway liegt nicht im Subnetz der IP-Adresse.",
+                        );
+                    }
+                    None => errors.add(
+                        format!("networks.{}.ip.netmask", i),
+                        "Ungültige Subnetzmaske.",
+                    ),
+                }
+
+                errors.check(
+                    static_ip.ip != static_ip.gateway,
+                    format!("networks.{}.ip.gateway", i),
+                    "Das Gateway darf nicht die IP-Adresse des Geräts sein.",
+                );
+            }
+        }
+
+        if let Some(ref hostname) = self.hostname {
+            let valid = (1..=32).contains(&hostname.len())
+                && hostname
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !hostname.starts_with('-')
+                && !hostname.ends_with('-');
+
+            errors.check(
+                valid,
+                "hostname",
+                "Der Hostname darf nur aus bis zu 32 Buchstaben, Ziffern und '-' bestehen.",
+            );
+        }
+
+        errors.into_result()
+    }
+
+    fn redact(&mut self) {
+        for network in &mut self.networks {
+            network.pass = None;
+        }
+    }
+
+    fn restore_secrets(&mut self, stored: &Self) {
+        for network in &mut self.networks {
+            if network.pass.is_some() {
+                continue;
+            }
+
+            if let Some(stored) = stored.networks.iter().find(|n| n.ssid == network.ssid) {
+                network.pass = stored.pass.clone();
+            }
+        }
+    }
+}
+
+impl From<WifiData> for WifiConfig {
+    fn from(data: WifiData) -> Self {
+        Self {
+            networks: data.networks,
+            hostname: data.hostname,
+        }
+    }
+}
+
+impl From<WifiData> for Command {
+    fn from(config: WifiData) -> Self {
+        Command::SaveWifiConfig(config)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RssData {
+    pub url: String,
+}
+
+impl<'de> ConfigData<'de> for RssData {
+    fn key() -> &'static str {
+        "rss"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match Url::parse(&self.url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+            Ok(_) => Err(ValidationErrors::single(
+                "url",
+                "Nur http- und https-URLs werden unterstützt.",
+            )),
+            Err(e) => Err(ValidationErrors::single(
+                "url",
+                format!("Ungültige URL: {}", e),
+            )),
+        }
+    }
+}
+
+impl From<RssData> for Command {
+    fn from(config: RssData) -> Self {
+        Command::SaveRssConfig(config)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StockData {
+    pub symbol: String,
+}
+
+impl<'de> ConfigData<'de> for StockData {
+    fn key() -> &'static str {
+        "stock"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        // The symbol can't be looked up online as there is no internet
+        // connection in setup mode, so only check its format.
+        let valid = (1..=10).contains(&self.symbol.len())
+            && self
+                .symbol
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+
+        if valid {
+            Ok(())
+        } else {
+            Err(ValidationErrors::single(
+                "symbol",
+                "Unbekanntes Symbol. Erlaubt sind bis zu 10 Buchstaben, Ziffern, '.' und '-'.",
+            ))
+        }
+    }
+}
+
+impl From<StockData> for Command {
+    fn from(config: StockData) -> Self {
+        Command::SaveStockConfig(config)
+    }
+}
+
+impl<'de> ConfigData<'de> for Schedule {
+    fn key() -> &'static str {
+        "schedule"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            (5..=720).contains(&self.refresh_interval),
+            "refresh_interval",
+            "Das Intervall muss zwischen 5 und 720 Minuten liegen.",
+        );
+        errors.check(
+            (30..=600).contains(&self.awake_time),
+            "awake_time",
+            "Die Wachzeit muss zwischen 30 und 600 Sekunden liegen.",
+        );
+        errors.check(
+            (5..=60).contains(&self.setup_time),
+            "setup_time",
+            "Die Setup-Dauer muss zwischen 5 und 60 Minuten liegen.",
+        );
+
+        if let Some(quiet_hours) = self.quiet_hours {
+            errors.check(
+                quiet_hours.start < 24,
+                "quiet_hours.start",
+                "Bitte eine Stunde zwischen 0 und 23 angeben.",
+            );
+            errors.check(
+                quiet_hours.end < 24,
+                "quiet_hours.end",
+                "Bitte eine Stunde zwischen 0 und 23 angeben.",
+            );
+            errors.check(
+                quiet_hours.start != quiet_hours.end,
+                "quiet_hours.end",
+                "Beginn und Ende der Ruhezeit dürfen nicht gleich sein.",
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+impl From<Schedule> for Command {
+    fn from(config: Schedule) -> Self {
+        Command::SaveScheduleConfig(config)
+    }
+}
+
+impl<'de> ConfigData<'de> for DisplayConfig {
+    fn key() -> &'static str {
+        "display"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            (1..=100).contains(&self.full_refresh_interval),
+            "full_refresh_interval",
+            "Bitte einen Wert zwischen 1 und 100 angeben.",
+        );
+        errors.check(
+            [0, 90, 180, 270].contains(&self.rotation),
+            "rotation",
+            "Die Drehung muss 0, 90, 180 oder 270 Grad betragen.",
+        );
+
+        errors.into_result()
+    }
+}
+
+impl From<DisplayConfig> for Command {
+    fn from(config: DisplayConfig) -> Self {
+        Command::SaveDisplayConfig(config)
+    }
+}
+
+impl<'de> ConfigData<'de> for AccessPointConfig {
+    fn key() -> &'static str {
+        "ap"
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        errors.check(
+            (1..=32).contains(&self.ssid.len()),
+            "ssid",
+            "Die SSID muss zwischen 1 und 32 Bytes lang sein.",
+        );
+        errors.check(
+            (8..=63).contains(&self.password.len())
+                && self.password.chars().all(|c| c.is_ascii_graphic()),
+            "password",
+            "Das Passwort muss aus 8 bis 63 druckbaren ASCII-Zeichen bestehen.",
+        );
+
+        errors.into_result()
+    }
+
+    fn redact(&mut self) {
+        self.password.clear();
+    }
+
+    fn restore_secrets(&mut self, stored: &Self) {
+        if self.password.is_empty() {
+            self.password = stored.password.clone();
+        }
+    }
+}
+
+impl From<AccessPointConfig> for Command {
+    fn from(config: AccessPointConfig) -> Self {
+        Command::SaveAccessPointConfig(config)
+    }
+}
+
+fn favicon_handler(_: &mut EspHttpRequest) -> Result<ResponseData> {
+    let favicon_path = &format!("{}/favicon.ico", BASE_DIR);
+    let favicon = File::open(favicon_path)
+        .with_context(|| format!("Could not find favicon: {}", favicon_path))?;
+
+    let mut response_data = ResponseData::new(200).body(favicon.into());
+    response_data.set_content_type("image/x-icon");
+
+    Ok(response_data)
+}
+
+fn read_body(req: &mut EspHttpRequest) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+
+    StdIO(req.reader())
+        .read_to_end(&mut body)
+        .context("Could not read request body.")?;
+
+    Ok(body)
+}
+
+/// Deserializes and validates a submitted configuration. Secrets that were
+/// left out are taken from the stored configuration.
+fn parse_config<T>(body: &[u8], nvs: &Mutex<NvsController>) -> Result<T, ValidationErrors>
+where
+    for<'de> T: ConfigData<'de>,
+{
+    let mut config: T = serde_json::from_slice(body)
+        .map_err(|e| ValidationErrors::single("body", format!("Ungültige Anfrage: {}", e)))?;
+
+    if let Ok(stored) = nvs.lock().unwrap().get_config::<T>() {
+        config.restore_secrets(&stored);
+    }
+
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// Checks the HTTP Basic credentials against the stored admin password.
+/// As long as no admin password is set every request is authorized.
+fn is_authorized(req: &EspHttpRequest, nvs: &Mutex<NvsController>) -> bool {
+    let credentials = match nvs.lock().unwrap().get_config::<AdminCredentials>() {
+        Ok(credentials) => credentials,
+        Err(_) => return true,
+    };
+
+    req.header("Authorization")
+        .and_then(|header| auth::basic_auth_password(&header))
+        .map(|password| credentials.verify(&password))
+        .unwrap_or(false)
+}
+
+fn unauthorized_response() -> ResponseData {
+    let mut response = ResponseData::new(401).body("Nicht autorisiert.".into());
+    response.set_header("WWW-Authenticate", "Basic realm=\"ESP-Feed\"");
+
+    response
+}
+
+/// Registers a handler that answers with 401 to unauthorized requests.
+fn set_protected_handler<F>(
+    server: &mut EspHttpServer,
+    uri: &str,
+    method: Method,
+    nvs: &Arc<Mutex<NvsController>>,
+    handler: F,
+) -> Result<()>
+where
+    F: Fn(&mut EspHttpRequest) -> Result<ResponseData> + 'static,
+{
+    let nvs = nvs.clone();
+
+    server.set_handler::<_, anyhow::Error>(uri, method, move |req| {
+        if !is_authorized(req, &nvs) {
+            return Ok(unauthorized_response());
+        }
+
+        handler(req)
+    })?;
+
+    Ok(())
+}
+
+fn set_settings_post_handler<T>(
+    server: &mut EspHttpServer,
+    uri: &str,
+    context: &ServerContext,
+) -> Result<()>
+where
+    for<'de> T: ConfigData<'de>,
+{
+    let nvs = context.nvs.clone();
+    let command_tx = context.command_tx.clone();
+
+    set_protected_handler(server, uri, Method::Post, &context.nvs, move |req| {
+        let body = read_body(req)?;
+
+        let form: T = match parse_config(&body, &nvs) {
+            Ok(form) => form,
+            Err(errors) => return api::json_response(400, &errors),
+        };
+        command_tx.send(form.into())?;
+
+        let resp = "Gespeichert!";
+        Ok(ResponseData::new(200).body(resp.into()))
+    })
+}
+
+fn set_settings_get_handler(server: &mut EspHttpServer, uri: &str, file: &str) -> Result<()> {
+    let path = format!("{}/settings/{}.htm", BASE_DIR, file);
+
+    server.set_handler::<_, anyhow::Error>(uri, Method::Get, move |_| {
+        let file = File::open(&path).with_context(|| format!("Could not find html: {}", path))?;
+
+        Ok(ResponseData::new(200).body(file.into()))
+    })?;
+
+    Ok(())
+}
+
+fn set_admin_post_handler(server: &mut EspHttpServer, context: &ServerContext) -> Result<()> {
+    let context = context.clone();
+
+    server.set_handler::<_, anyhow::Error>("/admin", Method::Post, move |req| {
+        let setup_mode = context.state.lock().unwrap().setup_mode;
+        let has_credentials = context
+            .nvs
+            .lock()
+            .unwrap()
+            .get_config::<AdminCredentials>()
+            .is_ok();
+
+        // The first password can only be set with physical access to the
+        // device, changing it later always requires the current one. The
+        // setup mode is also entered without physical access if the wifi
+        // connection fails.
+        if has_credentials {
+            if !is_authorized(req, &context.nvs) {
+                return Ok(unauthorized_response());
+            }
+        } else if !setup_mode {
+            let resp = "Das Passwort kann nur im Setup-Modus festgelegt werden.";
+            return Ok(ResponseData::new(403).body(resp.into()));
+        }
+
+        let body = read_body(req)?;
+
+        let form: AdminPassword = match serde_json::from_slice(&body) {
+            Ok(form) => form,
+            Err(e) => {
+                let errors = ValidationErrors::single("body", format!("Ungültige Anfrage: {}", e));
+                return api::json_response(400, &errors);
+            }
+        };
+        if let Err(errors) = form.validate() {
+            return api::json_response(400, &errors);
+        }
+
+        let credentials = AdminCredentials::new(&form.password);
+        context.command_tx.send(credentials.into())?;
+
+        let resp = "Gespeichert!";
+        Ok(ResponseData::new(200).body(resp.into()))
+    })?;
+
+    Ok(())
+}
+
+/// Redirects the captive portal probes to the settings, which makes the
+/// settings pop up as soon as a device joins the access point.
+fn set_captive_portal_handlers(server: &mut EspHttpServer) -> Result<()> {
+    for uri in CAPTIVE_PORTAL_PROBES {
+        server.set_handler::<_, anyhow::Error>(uri, Method::Get, |_| {
+            let mut response = ResponseData::new(302);
+            response.set_header("Location", &format!("http://{}/", AP_IP));
+
+            Ok(response)
+        })?;
+    }
+
+    Ok(())
+}
+
+pub fn httpd(context: ServerContext) -> Result<EspHttpServer> {
+    let mut server = EspHttpServer::new(&Configuration {
+        max_uri_handlers: 48,
+        ..Default::default()
+    })?;
+
+    server.set_handler::<_, anyhow::Error>("/simple", Method::Get, |_| {
+        let path = &format!("{}/simple.htm", BASE_DIR);
+        let file = File::open(path).with_context(|| format!("Could not open html: {}", path))?;
+
+        Ok(ResponseData::new(200).body(file.into()))
+    })?;
+    server.set_handler("/favicon.ico", Method::Get, favicon_handler)?;
+
+    set_settings_get_handler(&mut server, "/", "overview")?;
+    set_settings_get_handler(&mut server, "/personal", "personal")?;
+    set_settings_get_handler(&mut server, "/wifi", "wifi")?;
+    set_settings_get_handler(&mut server, "/rss", "rss")?;
+    set_settings_get_handler(&mut server, "/stock", "stock")?;
+    set_settings_get_handler(&mut server, "/schedule", "schedule")?;
+    set_settings_get_handler(&mut server, "/display", "display")?;
+    set_settings_get_handler(&mut server, "/admin", "admin")?;
+
+    set_settings_post_handler::<PersonalData>(&mut server, "/personal", &context)?;
+    set_settings_post_handler::<WifiData>(&mut server, "/wifi", &context)?;
+    set_settings_post_handler::<RssData>(&mut server, "/rss", &context)?;
+    set_settings_post_handler::<StockData>(&mut server, "/stock", &context)?;
+    set_settings_post_handler::<Schedule>(&mut server, "/schedule", &context)?;
+    set_settings_post_handler::<DisplayConfig>(&mut server, "/display", &context)?;
+    set_admin_post_handler(&mut server, &context)?;
+
+    api::set_handlers(&mut server, &context)?;
+
+    if context.state.lock().unwrap().setup_mode {
+        set_captive_portal_handlers(&mut server)?;
+    }
+
+    Ok(server)
+}
@@ -0,0 +1,48 @@
+//! Field level validation of the submitted configuration.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Maps the name of every invalid field to a message that can be shown next
+/// to the corresponding form input.
+#[derive(Serialize, Default, Debug)]
+pub struct ValidationErrors {
+    errors: BTreeMap<String, String>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn single(field: impl Into<String>, message: impl ToString) -> Self {
+        let mut errors = Self::new();
+        errors.add(field, message);
+        errors
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl ToString) {
+        self.errors.insert(field.into(), message.to_string());
+    }
+
+    /// Takes over the errors of a nested configuration, e.g. `wifi.ssid`.
+    pub fn merge(&mut self, prefix: &str, other: Self) {
+        for (field, message) in other.errors {
+            self.errors.insert(format!("{}.{}", prefix, field), message);
+        }
+    }
+
+    pub fn check(&mut self, valid: bool, field: impl Into<String>, message: &str) {
+        if !valid {
+            self.add(field, message);
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
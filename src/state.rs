@@ -1,33 +1,89 @@
 //! State struct that holds the complete state of the application.
 
 use crate::{
+    battery::BatteryStatus,
+    command::Refresh,
     feed::FeedController,
-    graphics::pages::{
-        ConfigPage, ExamplePage, FeedPage, Page, PageType, StockPage, WeatherPage, WeatherPageType,
+    graphics::{
+        pages::{
+            ConfigPage, ExamplePage, FeedPage, LowBatteryPage, Page, PageType, StockPage,
+            SystemPage, WeatherPage, WeatherPageType,
+        },
+        refresh::DisplayConfig,
     },
+    history::BatteryHistory,
+    schedule::Schedule,
     stock::StockController,
     weather::WeatherController,
-    wifi::WifiConfig,
+    wifi::{AccessPointConfig, WifiConfig},
 };
+use anyhow::Result;
+use serde::Serialize;
+use std::time::SystemTime;
+
+/// Outcome of the latest refresh of a single data source.
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct SourceStatus {
+    /// Unix time of the last successful refresh.
+    pub last_refresh: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl SourceStatus {
+    pub fn update(&mut self, result: Result<()>) {
+        match result {
+            Ok(()) => {
+                self.last_refresh = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .ok()
+                    .map(|unixtime| unixtime.as_secs());
+                self.last_error = None;
+            }
+            Err(e) => {
+                log::warn!("{:?}", e);
+                self.last_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct RefreshStatus {
+    pub feed: SourceStatus,
+    pub weather: SourceStatus,
+    pub stock: SourceStatus,
+}
 
 pub struct State {
     pub feed_controller: FeedController,
     pub weather_controller: WeatherController,
     pub stock_controller: StockController,
     pub setup_mode: bool,
+    /// Why the setup mode was entered if it wasn't requested by the user.
+    pub setup_reason: Option<String>,
+    pub access_point: AccessPointConfig,
+    /// Number of devices that joined the access point in setup mode.
+    pub access_point_clients: usize,
     pub page: PageType,
     pub wifi: Option<WifiConfig>,
     pub location: String,
-    pub battery: u16,
+    pub battery: BatteryStatus,
+    pub battery_history: BatteryHistory,
+    pub refresh_status: RefreshStatus,
+    pub schedule: Schedule,
+    pub display_config: DisplayConfig,
 }
 
 impl State {
     pub fn new(
         setup_mode: bool,
+        access_point: AccessPointConfig,
         wifi_config: Option<WifiConfig>,
         location: String,
         start_page: PageType,
-        stock_symbol: &str
+        stock_symbol: &str,
+        schedule: Schedule,
+        display_config: DisplayConfig,
     ) -> Self {
         let page = if setup_mode {
             ConfigPage.into()
@@ -40,16 +96,24 @@ impl State {
             weather_controller: WeatherController::new(),
             stock_controller: StockController::new(stock_symbol),
             setup_mode,
+            setup_reason: None,
+            access_point,
+            access_point_clients: 0,
             page,
             wifi: wifi_config,
             location,
-            battery: 0
+            battery: Default::default(),
+            battery_history: Default::default(),
+            refresh_status: Default::default(),
+            schedule,
+            display_config,
         }
     }
 
     pub fn next_page(&mut self) {
         match self.page.next_page() {
             PageType::ConfigPage(_) => self.page = ConfigPage.into(),
+            PageType::LowBatteryPage(_) => self.page = LowBatteryPage.into(),
             PageType::ExamplePage(_) => self.page = ExamplePage.into(),
             PageType::FeedPage(_) => self.page = FeedPage.into(),
             PageType::WeatherPage(WeatherPage(WeatherPageType::Daily)) => {
@@ -59,8 +123,58 @@ impl State {
                 self.page = WeatherPage(WeatherPageType::Hourly).into()
             }
             PageType::StockPage(_) => self.page = StockPage.into(),
+            PageType::SystemPage(_) => self.page = SystemPage.into(),
         }
 
         log::info!("Switched page to {:?}", self.page);
     }
+
+    /// Whether there is any data of the source to show.
+    pub fn has_data(&self, source: Refresh) -> bool {
+        source.sources().iter().all(|source| match source {
+            Refresh::Feed => !self.feed_controller.feeds().is_empty(),
+            Refresh::Weather => self.weather_controller.current().is_some(),
+            Refresh::Stock => self.stock_controller.stock_data().is_some(),
+            Refresh::All | Refresh::Essential => unreachable!(),
+        })
+    }
+
+    /// Restores the data of the last run, e.g. after waking up from deep sleep.
+    pub fn load_cache(&mut self) {
+        let history = BatteryHistory::load().map(|history| self.battery_history = history);
+
+        let results = [
+            self.feed_controller.load_cache(),
+            self.weather_controller.load_cache(),
+            self.stock_controller.load_cache(),
+            history,
+        ];
+
+        for result in results {
+            if let Err(e) = result {
+                log::info!("{:#}", e);
+            }
+        }
+    }
+
+    /// Stores the data of the refreshed sources so it survives the deep sleep.
+    pub fn store_cache(&self, source: Refresh) {
+        for &source in source.sources() {
+            // Keep the old data if the refresh failed.
+            if !self.has_data(source) {
+                continue;
+            }
+
+            let result = match source {
+                Refresh::Feed => self.feed_controller.store_cache(),
+                Refresh::Weather => self.weather_controller.store_cache(),
+                Refresh::Stock => self.stock_controller.store_cache(),
+                Refresh::All | Refresh::Essential => unreachable!(),
+            };
+
+            if let Err(e) = result {
+                log::warn!("{:?}", e);
+            }
+        }
+    }
 }
@@ -1,7 +1,7 @@
 //! Fetches the stock info and provides the StockController.
 
-use crate::datetime;
-use anyhow::Result;
+use crate::{cache, datetime};
+use anyhow::{Context, Result};
 use embedded_plots::curve::PlotPoint;
 use embedded_svc::{
     http::client::{Client, Request},
@@ -89,6 +89,12 @@ impl StockController {
         }
     }
 
+    /// Changes the symbol and drops the data of the previous one.
+    pub fn set_symbol(&mut self, symbol: &str) {
+        self.symbol = symbol.into();
+        self.plot_points = None;
+    }
+
     pub fn stock_data(&self) -> Option<&[PlotPoint]> {
         if let Some(ref data) = self.plot_points {
             Some(data.as_slice())
@@ -97,6 +103,32 @@ impl StockController {
         }
     }
 
+    pub fn store_cache(&self) -> Result<()> {
+        let points: Option<Vec<(i32, i32)>> = self
+            .plot_points
+            .as_ref()
+            .map(|points| points.iter().map(|point| (point.x, point.y)).collect());
+
+        cache::store("stock", &(&self.symbol, points))
+    }
+
+    /// Only restores the data if it belongs to the current symbol.
+    pub fn load_cache(&mut self) -> Result<()> {
+        let (symbol, points): (String, Option<Vec<(i32, i32)>>) = cache::load("stock")?;
+
+        if symbol == self.symbol {
+            self.plot_points = match points {
+                Some(points) => Some(
+                    array_init::from_iter(points.into_iter().map(|(x, y)| PlotPoint { x, y }))
+                        .context("Cached stock data is incomplete.")?,
+                ),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
     pub fn refresh(&mut self) -> Result<()> {
         let url = url::Url::parse(&format!(
             "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&symbol={}&apikey={}",
@@ -2,6 +2,7 @@
 
 mod openweather_types;
 
+use crate::cache;
 use anyhow::Result;
 use embedded_svc::{
     http::client::{Client, Request},
@@ -12,6 +13,7 @@ use openweather_types::{Coord, Daily, Hourly, OpenWeather, OpenWeatherOnecall};
 use std::{
     convert::{TryFrom, TryInto},
     io::BufReader,
+    time::SystemTime,
 };
 
 const OPENWEATHER_API_KEY: &str = env!("OPENWEATHER_API_KEY");
@@ -130,6 +132,18 @@ impl WeatherController {
         Ok(())
     }
 
+    pub fn store_cache(&self) -> Result<()> {
+        cache::store("weather", &(&self.current_report, &self.forecast))
+    }
+
+    pub fn load_cache(&mut self) -> Result<()> {
+        let (current_report, forecast) = cache::load("weather")?;
+        self.current_report = current_report;
+        self.forecast = forecast;
+
+        Ok(())
+    }
+
     pub fn current(&self) -> Option<WeatherReport> {
         let current_report = self.current_report.as_ref()?;
 
@@ -150,6 +164,21 @@ impl WeatherController {
         day_report.try_into().ok()
     }
 
+    /// Event name of the first weather warning that has not ended yet.
+    pub fn alert(&self) -> Option<&str> {
+        let forecast = self.forecast.as_ref()?;
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs() as i64;
+
+        forecast
+            .alerts
+            .iter()
+            .find(|alert| alert.end > now)
+            .map(|alert| alert.event.as_str())
+    }
+
     fn fetch_current_weather(&self, location: &str) -> Result<OpenWeather> {
         let url = url::Url::parse(&format!(
             "https://api.openweathermap.org/data/2.5/weather?q={}&APPID={}&lang=de&units=metric",
@@ -167,7 +196,7 @@ impl WeatherController {
 
     fn fetch_forecast(&self, location: &Coord) -> Result<OpenWeatherOnecall> {
         let url = url::Url::parse(&format!(
-            "https://api.openweathermap.org/data/2.5/onecall?lat={}&lon={}&APPID={}&lang=de&units=metric&exclude=current,minutely,alerts",
+            "https://api.openweathermap.org/data/2.5/onecall?lat={}&lon={}&APPID={}&lang=de&units=metric&exclude=current,minutely",
             location.lat, location.lon, OPENWEATHER_API_KEY
         ))
         .expect("Invalid Url");
@@ -6,18 +6,18 @@
 
 use serde::{
     de::{Error, IgnoredAny, SeqAccess, Visitor},
-    Deserialize, Deserializer,
+    Deserialize, Deserializer, Serialize,
 };
 use std::{fmt, marker::PhantomData};
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Coord {
     pub lat: f32,
     pub lon: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Description {
     // pub id: i32,
@@ -26,7 +26,7 @@ pub struct Description {
     pub icon: String,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Main {
     // #[serde(flatten)]
@@ -42,7 +42,7 @@ pub struct Main {
     // pub grnd_level: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Temperature {
     pub temp: f32,
@@ -51,7 +51,7 @@ pub struct Temperature {
     pub feels_like: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct TemperatureDaily {
     pub day: f32,
@@ -62,7 +62,7 @@ pub struct TemperatureDaily {
     pub morn: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct FeelsLikeDaily {
     pub day: f32,
@@ -71,7 +71,7 @@ pub struct FeelsLikeDaily {
     pub morn: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Wind {
     pub speed: f32,
@@ -79,7 +79,7 @@ pub struct Wind {
     pub gust: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct WindOnecall {
     #[serde(rename = "wind_speed")]
@@ -90,7 +90,7 @@ pub struct WindOnecall {
     pub gust: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Precipitation {
     #[serde(rename = "1h")]
@@ -99,20 +99,20 @@ pub struct Precipitation {
     pub three_hour: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Clouds {
     pub all: i32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Sun {
     pub sunrise: i64,
     pub sunset: i64,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Moon {
     pub moonrise: i64,
@@ -120,7 +120,7 @@ pub struct Moon {
     pub moon_phase: f32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Sys {
     pub r#type: i32,
@@ -132,7 +132,7 @@ pub struct Sys {
     pub sunset: i64,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Hourly {
     pub dt: i64,
@@ -153,7 +153,7 @@ pub struct Hourly {
     pub weather: [Description; 1],
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Daily {
     pub dt: i64,
@@ -172,7 +172,7 @@ pub struct Daily {
     pub weather: [Description; 1],
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct OpenWeather {
     pub coord: Coord,
@@ -193,15 +193,29 @@ pub struct OpenWeather {
     pub cod: i32,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Alert {
+    // pub sender_name: String,
+    pub event: String,
+    pub start: i64,
+    pub end: i64,
+    // pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct OpenWeatherOnecall {
     // #[serde(flatten)]
     // pub coord: Coord,
+    /// Enough for the widest forecast row, the first one is the current hour.
     #[serde(deserialize_with = "deserialize_first")]
-    pub hourly: [Hourly; 6],
+    pub hourly: [Hourly; 13],
+    /// Today and the next seven days, as many as the API returns.
     #[serde(deserialize_with = "deserialize_first")]
-    pub daily: [Daily; 6],
+    pub daily: [Daily; 8],
+    /// Only present if there are official weather warnings for the location.
+    pub alerts: Vec<Alert>,
     // pub timezone: String,
     // pub timezone_offset: f32,
 }
@@ -2,7 +2,7 @@
 
 use anyhow::*;
 use embedded_svc::{
-    ipv4::DHCPClientSettings,
+    ipv4::{self, DHCPClientSettings, Mask, Subnet},
     wifi::{
         AccessPointConfiguration, ApIpStatus, ApStatus, AuthMethod, ClientConfiguration,
         ClientConnectionStatus, ClientIpStatus, ClientStatus, Configuration, Status, Wifi,
@@ -13,14 +13,215 @@ use esp_idf_svc::{
 };
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    fmt,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_HOSTNAME: &str = "esp-feed";
+
+/// How long to wait for a connection to a single network.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often all known networks are tried before giving up.
+const CONNECT_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The wifi driver, shared so that e.g. the web server can scan for networks.
+pub type SharedWifi = Arc<Mutex<Option<EspWifi>>>;
+
+/// Fixed IPv4 settings that are used instead of DHCP.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StaticIp {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    #[serde(default)]
+    pub dns: Option<Ipv4Addr>,
+    #[serde(default)]
+    pub secondary_dns: Option<Ipv4Addr>,
+}
+
+impl StaticIp {
+    /// Length of the network prefix or `None` if the netmask is invalid. A /0
+    /// or /32 network has no room for both the device and a gateway.
+    pub fn prefix_len(&self) -> Option<u8> {
+        let mask = u32::from(self.netmask);
+        let len = mask.leading_ones();
+
+        if (1..32).contains(&len) && mask.checked_shl(len).unwrap_or(0) == 0 {
+            Some(len as u8)
+        } else {
+            None
+        }
+    }
+
+    fn client_configuration(&self) -> ipv4::ClientConfiguration {
+        ipv4::ClientConfiguration::Fixed(ipv4::ClientSettings {
+            ip: self.ip,
+            subnet: Subnet {
+                gateway: self.gateway,
+                mask: Mask(self.prefix_len().unwrap_or(24)),
+            },
+            dns: self.dns,
+            secondary_dns: self.secondary_dns,
+        })
+    }
+}
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    /// An empty key is an open network. `None` keeps the stored key when the
+    /// config is submitted, it is left out when the config leaves the device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    /// Use DHCP if not set.
+    #[serde(default)]
+    pub ip: Option<StaticIp>,
+}
+
+/// Leaves out the key, configs are written to the log when they are saved.
+impl fmt::Debug for WifiNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WifiNetwork")
+            .field("ssid", &self.ssid)
+            .field("pass", &self.pass.as_ref().map(|_| "<redacted>"))
+            .field("ip", &self.ip)
+            .finish()
+    }
+}
+
+/// All known networks, the strongest one in range is used.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WifiConfig {
+    pub networks: Vec<WifiNetwork>,
+    pub hostname: Option<String>,
+}
+
+/// Credentials of the access point that is opened in setup mode.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessPointConfig {
+    pub ssid: String,
+    pub password: String,
+}
+
+impl AccessPointConfig {
+    /// Creates credentials that are unique for this device: the SSID ends
+    /// with the last three bytes of the MAC and the password is random.
+    pub fn generate() -> Self {
+        // Leave out characters that are easily confused on the display.
+        const CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyzACDEFGHJKLMNPQRSTUVWXYZ23456789";
+        const PASSWORD_LEN: usize = 10;
+
+        let mut mac = [0u8; 6];
+        let result = unsafe {
+            esp_idf_sys::esp_read_mac(
+                mac.as_mut_ptr(),
+                esp_idf_sys::esp_mac_type_t_ESP_MAC_WIFI_SOFTAP,
+            )
+        };
+
+        // Random bytes still keep the SSIDs of several devices apart.
+        if result != esp_idf_sys::ESP_OK as esp_idf_sys::esp_err_t {
+            warn!(
+                "Could not read the MAC address, use a random SSID: {}",
+                result
+            );
+            mac.iter_mut()
+                .for_each(|byte| *byte = unsafe { esp_idf_sys::esp_random() } as u8);
+        }
+
+        let password = (0..PASSWORD_LEN)
+            .map(|_| {
+                let random = unsafe { esp_idf_sys::esp_random() } as usize;
+                CHARSET[random % CHARSET.len()] as char
+            })
+            .collect();
+
+        Self {
+            ssid: format!("ESP-Feed-{:02X}{:02X}{:02X}", mac[3], mac[4], mac[5]),
+            password,
+        }
+    }
+
+    /// Text of a QR code that lets phones join the access point.
+    pub fn join_uri(&self) -> String {
+        fn escape(value: &str) -> String {
+            value
+                .chars()
+                .flat_map(|c| match c {
+                    '\\' | ';' | ',' | ':' | '"' => vec!['\\', c],
+                    c => vec![c],
+                })
+                .collect()
+        }
+
+        format!(
+            "WIFI:S:{};T:WPA;P:{};;",
+            escape(&self.ssid),
+            escape(&self.password)
+        )
+    }
+}
+
+/// Number of stations that are connected to the access point.
+pub fn accesspoint_station_count() -> usize {
+    let mut stations: esp_idf_sys::wifi_sta_list_t = Default::default();
+
+    let result = unsafe { esp_idf_sys::esp_wifi_ap_get_sta_list(&mut stations) };
+
+    if result == esp_idf_sys::ESP_OK as esp_idf_sys::esp_err_t {
+        stations.num as usize
+    } else {
+        0
+    }
+}
+
+/// A network that was found during a scan.
+#[derive(Serialize, Debug, Clone)]
+pub struct ScannedNetwork {
     pub ssid: String,
-    pub pass: String,
+    /// Signal strength in dBm.
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth_method: String,
+}
+
+/// Scans for networks. Every SSID is only listed once with its strongest
+/// access point, sorted by signal strength. Hidden networks are left out.
+pub fn scan(wifi: &mut EspWifi) -> Result<Vec<ScannedNetwork>> {
+    let mut networks: Vec<ScannedNetwork> = Vec::new();
+
+    for ap_info in wifi.scan()? {
+        if ap_info.ssid.is_empty() {
+            continue;
+        }
+
+        let network = ScannedNetwork {
+            ssid: ap_info.ssid,
+            rssi: ap_info.signal_strength as i8,
+            channel: ap_info.channel,
+            auth_method: format!("{:?}", ap_info.auth_method),
+        };
+
+        match networks.iter_mut().find(|known| known.ssid == network.ssid) {
+            Some(known) if known.rssi < network.rssi => *known = network,
+            Some(_) => {}
+            None => networks.push(network),
+        }
+    }
+
+    networks.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+
+    Ok(networks)
 }
 
+/// Connects to the strongest known network in range. If that fails the
+/// next one is tried, networks that were not found during the scan come
+/// last as they might be hidden. All networks are tried a few times before
+/// an error is returned.
 pub fn connect(
     wifi_config: Option<&WifiConfig>,
     netif_stack: Arc<EspNetifStack>,
@@ -29,35 +230,85 @@ pub fn connect(
 ) -> Result<EspWifi> {
     let mut wifi = EspWifi::new(netif_stack, sys_loop_stack, default_nvs)?;
 
-    info!("Wifi created, about to scan");
-    let WifiConfig { ssid, pass } = wifi_config.ok_or(anyhow!("No valid wifi config."))?;
+    let WifiConfig { networks, hostname } = wifi_config.ok_or(anyhow!("No valid wifi config."))?;
+    let hostname = hostname.as_deref().unwrap_or(DEFAULT_HOSTNAME);
 
-    let ap_info_list = wifi.scan()?;
-    let ap_info = ap_info_list.into_iter().find(|a| a.ssid == *ssid);
+    for attempt in 1..=CONNECT_ATTEMPTS {
+        if attempt > 1 {
+            std::thread::sleep(RETRY_DELAY);
+        }
 
-    let channel = if let Some(ap_info) = ap_info {
-        info!(
-            "Found configured access point {} on channel {}",
-            ssid, ap_info.channel
-        );
-        Some(ap_info.channel)
-    } else {
-        warn!(
-            "Configured access point {} not found during scanning, will go with unknown channel",
-            ssid
-        );
-        None
+        info!("Wifi created, about to scan (attempt {})", attempt);
+        // Without a scan all known networks are tried on an unknown channel.
+        let scanned_networks = scan(&mut wifi).unwrap_or_else(|e| {
+            warn!("{:?}", e.context("Could not scan for networks."));
+            Vec::new()
+        });
+
+        for (network, channel) in candidates(networks, &scanned_networks) {
+            match connect_network(&mut wifi, network, channel, hostname) {
+                Ok(()) => return Ok(wifi),
+                Err(e) => warn!("{:?}", e),
+            }
+        }
+    }
+
+    let ssids: Vec<&str> = networks.iter().map(|n| n.ssid.as_str()).collect();
+    bail!("Could not connect to {}.", ssids.join(", "))
+}
+
+/// Orders the known networks by signal strength together with the channel
+/// they were found on.
+fn candidates<'a>(
+    networks: &'a [WifiNetwork],
+    scanned_networks: &[ScannedNetwork],
+) -> Vec<(&'a WifiNetwork, Option<u8>)> {
+    let mut candidates: Vec<(&WifiNetwork, Option<u8>)> = scanned_networks
+        .iter()
+        .filter_map(|scanned| {
+            let network = networks.iter().find(|n| n.ssid == scanned.ssid)?;
+            info!(
+                "Found known access point {} on channel {} with {} dBm",
+                scanned.ssid, scanned.channel, scanned.rssi
+            );
+            Some((network, Some(scanned.channel)))
+        })
+        .collect();
+
+    for network in networks {
+        if !candidates.iter().any(|(n, _)| n.ssid == network.ssid) {
+            warn!(
+                "Known access point {} not found during scanning, will go with unknown channel",
+                network.ssid
+            );
+            candidates.push((network, None));
+        }
+    }
+
+    candidates
+}
+
+fn connect_network(
+    wifi: &mut EspWifi,
+    network: &WifiNetwork,
+    channel: Option<u8>,
+    hostname: &str,
+) -> Result<()> {
+    info!("Connecting to {}", network.ssid);
+
+    // The hostname is only announced through DHCP.
+    let ip_conf = match network.ip {
+        Some(ref static_ip) => static_ip.client_configuration(),
+        None => ipv4::ClientConfiguration::DHCP(DHCPClientSettings {
+            hostname: Some(hostname.into()),
+        }),
     };
 
     let configuration = Configuration::Client(ClientConfiguration {
-        ssid: ssid.clone(),
-        password: pass.clone(),
+        ssid: network.ssid.clone(),
+        password: network.pass.clone().unwrap_or_default(),
         channel,
-        ip_conf: Some(embedded_svc::ipv4::ClientConfiguration::DHCP(
-            DHCPClientSettings {
-                hostname: Some("esp-feed".into()),
-            },
-        )),
+        ip_conf: Some(ip_conf),
         ..Default::default()
     });
 
@@ -65,39 +316,55 @@ pub fn connect(
 
     info!("Wifi configuration set, about to get status");
 
-    let status = wifi.get_status();
+    let start = Instant::now();
 
-    if let Status(
-        ClientStatus::Started(ClientConnectionStatus::Connected(ClientIpStatus::Done(ip_settings))),
-        _,
-    ) = &status
-    {
-        info!("Wifi connected!");
-        info!(
-            "My IP is {}, Subnet: {}, DNS: {:?}",
-            ip_settings.ip,
-            ip_settings.subnet.to_string(),
-            ip_settings.dns
-        );
-    }
+    loop {
+        if let Status(
+            ClientStatus::Started(ClientConnectionStatus::Connected(ClientIpStatus::Done(
+                ip_settings,
+            ))),
+            _,
+        ) = wifi.get_status()
+        {
+            info!("Wifi connected!");
+            info!(
+                "My IP is {}, Subnet: {}, DNS: {:?}",
+                ip_settings.ip,
+                ip_settings.subnet.to_string(),
+                ip_settings.dns
+            );
 
-    Ok(wifi)
+            return Ok(());
+        }
+
+        if start.elapsed() > CONNECT_TIMEOUT {
+            bail!("Timeout while connecting to {}", network.ssid);
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
 }
 
 pub fn create_accesspoint(
     netif_stack: Arc<EspNetifStack>,
     sys_loop_stack: Arc<EspSysLoopStack>,
     default_nvs: Arc<EspDefaultNvs>,
+    config: &AccessPointConfig,
 ) -> Result<EspWifi> {
     let mut wifi = EspWifi::new(netif_stack, sys_loop_stack, default_nvs)?;
 
-    let configuration = Configuration::AccessPoint(AccessPointConfiguration {
-        ssid: "ESP-Feed".into(),
-        channel: 1,
-        auth_method: AuthMethod::WPA2Personal,
-        password: "38294446".into(),
-        ..Default::default()
-    });
+    // The client part is not connected to anything but is needed to be
+    // able to scan for networks while the access point is running.
+    let configuration = Configuration::Mixed(
+        ClientConfiguration::default(),
+        AccessPointConfiguration {
+            ssid: config.ssid.clone(),
+            channel: 1,
+            auth_method: AuthMethod::WPA2Personal,
+            password: config.password.clone(),
+            ..Default::default()
+        },
+    );
 
     wifi.set_configuration(&configuration)?;
 